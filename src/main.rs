@@ -1,8 +1,8 @@
-mod polynomial;
-use polynomial::Polynomial;
+use polynomial_multiplication::polynomial::{MulThresholds, Polynomial, RoundingPolicy};
 use polars::prelude::*;
 use std::time::Instant;
 
+#[allow(dead_code)] // only used when the benchmark in main is uncommented
 fn run_performance_test(runs:usize) -> Result<DataFrame, PolarsError> {
     let mut regular_mul:Vec<f64> = Vec::with_capacity(runs);
     let mut fft_mul:Vec<f64> = Vec::with_capacity(runs);
@@ -39,7 +39,66 @@ fn run_performance_test(runs:usize) -> Result<DataFrame, PolarsError> {
     DataFrame::new(vec![regular, fft, fft_threaded])
 }
 
+// Smallest of a few timings of f, in ms
+fn time_ms<R>(repeats:usize, mut f:impl FnMut() -> R) -> f64 {
+    (0..repeats).map(|_| {
+        let now = Instant::now();
+        std::hint::black_box(f());
+        (now.elapsed().as_secs_f64() * 1e9).round() / 1e6
+    }).fold(f64::INFINITY, f64::min)
+}
+
+// Times the algorithms p * q chooses between at each size, to find the defaults of MulThresholds.
+// Each column times the product of two polynomials with len coefficients:
+// karatsuba and toom3 take one step and hand the halves or thirds to the algorithm below them,
+// so each is compared with the column to its left. The *_full columns recurse all the way.
+fn run_threshold_test(sizes:&[usize], repeats:usize) -> Result<DataFrame, PolarsError> {
+    let mut columns:Vec<Vec<f64>> = vec![Vec::new(); 8];
+    for &n in sizes {
+        let p1 = Polynomial::from_vec((0..n).map(|i| ((i * 7) % 11) as i64 - 5).collect());
+        let p2 = Polynomial::from_vec((0..n).map(|i| ((i * 5) % 13) as i64 - 6).collect());
+        let one_toom3_step = MulThresholds{toom3: n, ..MulThresholds::DEFAULT};
+        let recursive = MulThresholds{ntt: usize::MAX, ..MulThresholds::DEFAULT};
+        let f1 = Polynomial::from_vec((0..n).map(|i| ((i * 7) % 11) as f64 - 5.).collect());
+        let f2 = Polynomial::from_vec((0..n).map(|i| ((i * 5) % 13) as f64 - 6.).collect());
+        let times = [
+            time_ms(repeats, || p1.multiply(&p2)),
+            time_ms(repeats, || p1.karatsuba_mul_with_cutoff(&p2, n)),
+            time_ms(repeats, || p1.karatsuba_mul_with_cutoff(&p2, MulThresholds::DEFAULT.karatsuba)),
+            time_ms(repeats, || p1.toom3_mul_with(&p2, &one_toom3_step)),
+            time_ms(repeats, || p1.mul_with(&p2, &recursive)),
+            time_ms(repeats, || p1.ntt_mul(&p2).unwrap()),
+            time_ms(repeats, || f1.multiply(&f2)),
+            time_ms(repeats, || f1.fft_mul(&f2, RoundingPolicy::None)),
+        ];
+        for (column, time) in columns.iter_mut().zip(times) {
+            column.push(time);
+        }
+    }
+    let names = ["schoolbook_i64 (ms)", "karatsuba_i64 (ms)", "karatsuba_full_i64 (ms)", "toom3_i64 (ms)", "toom3_full_i64 (ms)", "ntt_i64 (ms)", "schoolbook_f64 (ms)", "fft_f64 (ms)"];
+    let mut series = vec![Series::from_vec("len", sizes.iter().map(|n| *n as u64).collect::<Vec<u64>>())];
+    series.extend(names.iter().zip(columns).map(|(name, column)| Series::from_vec(name, column)));
+    DataFrame::new(series)
+}
+
 fn main() {
+    // cargo run -r thresholds: measures the crossovers behind MulThresholds::DEFAULT into threshold_results.csv
+    if std::env::args().nth(1).as_deref() == Some("thresholds") {
+        let sizes:Vec<usize> = (3..=13).flat_map(|k| [1 << k, 3 << (k - 1)]).filter(|n| *n <= 8192).collect();
+        match run_threshold_test(&sizes, 25) {
+            Ok(mut df) => {
+                use std::fs::File;
+                let mut file = File::create("threshold_results.csv").expect("Could not create file");
+                if CsvWriter::new(&mut file).has_header(true).with_delimiter(b',').finish(&mut df).is_err() {
+                    println!("Error happened when writing to csv.");
+                }
+                println!("{}", df);
+            }
+            Err(e) => println!("Some error occured during the test: {}", e)
+        }
+        return
+    }
+
     // // Trying to see when will FFT be faster than regular multiplication.
     // let args: Vec<String> = env::args().collect();
     // let n = args[1].parse::<usize>().unwrap();
@@ -98,6 +157,29 @@ fn main() {
 #[cfg(test)]
 mod test {
     use super::*;
+    use ndarray::{Array1, ArrayView1, array, s};
+    use num_complex::{Complex32, Complex64};
+    use num_traits::NumCast;
+    use polynomial_multiplication::polynomial::{ConvolveMode, FftFloat, FftPlan, ModInt, MulAlgorithm, MulDispatch, MulThresholds, Parallelism, PolynomialError, PreparedPolynomial, RoundingPolicy, StreamingConvolver, StreamingMethod, P998244353, PLAN_CACHE_CAPACITY, fft, inverse_fft, ntt, inverse_ntt, ring_fft, inverse_ring_fft, convolve, correlate};
+
+    // The coefficients most tests multiply: ((i * step) % modulus) - offset for i in 0..n, small numbers of
+    // both signs without an obvious pattern. Most tests use (7, 11, 5) for one operand and (5, 13, 6) for the other.
+    fn fixture<T:NumCast>(n:usize, step:usize, modulus:usize, offset:i64) -> Vec<T> {
+        (0..n).map(|i| T::from(((i * step) % modulus) as i64 - offset).unwrap()).collect()
+    }
+
+    // fixture(n, 7, 11, 5) with the imaginary parts fixture(n, 3, 5, 0)
+    fn complex_fixture(n:usize) -> Vec<Complex64> {
+        fixture::<f64>(n, 7, 11, 5).into_iter().zip(fixture::<f64>(n, 3, 5, 0)).map(|(re, im)| Complex64::new(re, im)).collect()
+    }
+
+    // FFT output is only accurate up to rounding, so compare coefficient by coefficient.
+    fn assert_close(p1:&Polynomial<f64>, p2:&Polynomial<f64>, tol:f64) {
+        assert_eq!(p1.len(), p2.len(), "{} and {} have different lengths", p1, p2);
+        for (a, b) in p1.get_coeffs().iter().zip(p2.get_coeffs().iter()) {
+            assert!((a - b).abs() < tol, "{} and {} differ by more than {}", p1, p2, tol);
+        }
+    }
 
     #[test]
     fn test_add_1() {
@@ -143,6 +225,239 @@ mod test {
         assert_eq!(p1.multiply(&p2), Polynomial::from_vec(vec![0]));
     }
 
+    #[test]
+    fn test_mul_dispatch_1() {
+        let p1 = Polynomial::from_vec(fixture::<f64>(400, 7, 11, 5));
+        let p2 = Polynomial::from_vec(fixture::<f64>(300, 5, 13, 6));
+        assert_eq!(p1.mul_algorithm(&p2, &MulThresholds::DEFAULT), MulAlgorithm::Fft);
+        assert_close(&p1.multiply(&p2), &(&p1 * &p2), 1e-6);
+        // small operands stay exact
+        let p3 = Polynomial::from_vec(vec![0.1, 0.2, 0.3]);
+        assert_eq!(p3.mul_algorithm(&p2, &MulThresholds::DEFAULT), MulAlgorithm::Schoolbook);
+        assert_eq!(p3.multiply(&p2), &p3 * &p2);
+    }
+
+    #[test]
+    fn test_mul_dispatch_2() {
+        let p1 = Polynomial::from_vec(vec![-1.,1.]);
+        let p2 = Polynomial::from_vec(vec![1.,1.,1.,1.,1.,1.,1.]);
+        let thresholds = MulThresholds{fft: 2, ..MulThresholds::DEFAULT};
+        assert_eq!(p1.mul_algorithm(&p2, &thresholds), MulAlgorithm::Fft);
+        assert_close(&p1.multiply(&p2), &p1.mul_with(&p2, &thresholds), 1e-9);
+        // integers have no FFT
+        let p3 = Polynomial::from_vec(vec![-1,1]);
+        assert_eq!(p3.mul_algorithm(&p3, &MulThresholds{karatsuba: 2, fft: 2, ..MulThresholds::DEFAULT}), MulAlgorithm::Karatsuba);
+        assert_eq!(p3.mul_algorithm(&p3, &thresholds), MulAlgorithm::Schoolbook);
+    }
+
+    // Operand sizes just below, at and just above MulThresholds::DEFAULT.karatsuba, toom3 and ntt.
+    const THRESHOLD_SIZES:[usize; 9] = [63, 64, 65, 511, 512, 513, 2047, 2048, 2049];
+
+    // p * q and square against multiply, with the fixtures times scale. Unsigned types get the fixtures without offsets.
+    fn assert_mul_dispatch_exact<T>(sizes:&[usize], scale:T, signed:bool) 
+        where T: MulDispatch + NumCast + std::fmt::Debug
+    {
+        let (offset_a, offset_b) = if signed {(5, 6)} else {(0, 0)};
+        for &n in sizes {
+            let p1 = Polynomial::from_vec(fixture::<T>(n, 7, 11, offset_a).into_iter().map(|x| x * scale).collect());
+            let p2 = Polynomial::from_vec(fixture::<T>(n + 1, 5, 13, offset_b).into_iter().map(|x| x * scale).collect());
+            assert_eq!(p1.multiply(&p2), &p1 * &p2, "{} coefficients", n);
+            assert_eq!(p1.multiply(&p1), p1.square(), "{} coefficients", n);
+        }
+    }
+
+    #[test]
+    fn test_mul_dispatch_3() {
+        // small coefficients, every integer type through every algorithm it has
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES[..6], 1i16, true);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES[..6], 1u16, false);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1i32, true);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1u32, false);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1i64, true);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1u64, false);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1i128, true);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1isize, true);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1usize, false);
+    }
+
+    #[test]
+    fn test_mul_dispatch_4() {
+        // coefficients large enough that the largest product coefficient is close to the limit of the type
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1i32 << 7, true);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1u32 << 6, false);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1i64 << 23, true);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1u64 << 22, false);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1i128 << 55, true);
+        assert_mul_dispatch_exact(&THRESHOLD_SIZES, 1u128 << 55, false);
+    }
+
+    #[test]
+    fn test_karatsuba_1() {
+        let p1 = Polynomial::from_vec(fixture::<i64>(157, 7, 11, 5));
+        let p2 = Polynomial::from_vec(fixture::<i64>(90, 5, 13, 6));
+        assert_eq!(p1.multiply(&p2), p1.karatsuba_mul_with_cutoff(&p2, 2));
+        assert_eq!(p1.multiply(&p2), p1.karatsuba_mul_with_cutoff(&p2, 8));
+        assert_eq!(p1.multiply(&p2), &p1 * &p2);
+        // very unbalanced, unsigned
+        let p3 = Polynomial::from_vec((0..500).map(|i| (i % 3) as u64).collect());
+        let p4 = Polynomial::from_vec(vec![1u64, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(p3.multiply(&p4), p3.karatsuba_mul_with_cutoff(&p4, 2));
+        assert_eq!(p4.multiply(&p3), p4.karatsuba_mul_with_cutoff(&p3, 2));
+    }
+
+    #[test]
+    fn test_karatsuba_2() {
+        // Every coefficient of the product fits in i16 and u16, but (a0 + a1)(b0 + b1) would not,
+        // so p * q has to stay with schoolbook.
+        let p1 = Polynomial::from_vec(vec![22i16; 64]);
+        let p2 = Polynomial::from_vec(vec![23i16; 64]);
+        assert_eq!(p1.mul_algorithm(&p2, &MulThresholds::DEFAULT), MulAlgorithm::Karatsuba);
+        assert_eq!(p1.multiply(&p2), &p1 * &p2);
+        let p3 = Polynomial::from_vec(vec![31u16; 64]);
+        assert_eq!(p3.multiply(&p3), &p3 * &p3);
+        assert_eq!(p3.multiply(&p3), p3.square());
+        // with enough headroom it is still Karatsuba, which gives the same product
+        let p4 = Polynomial::from_vec(vec![-1i16; 64]);
+        let p5 = Polynomial::from_vec(vec![3i16; 64]);
+        assert_eq!(p4.karatsuba_mul_with_cutoff(&p5, 2), &p4 * &p5);
+    }
+
+    #[test]
+    fn test_toom3_1() {
+        let thresholds = MulThresholds{karatsuba: 2, toom3: 3, ..MulThresholds::DEFAULT};
+        for (n, m) in [(3, 3), (4, 3), (10, 9), (157, 90), (300, 40), (64, 200)] {
+            let p1 = Polynomial::from_vec(fixture::<i64>(n, 7, 11, 5));
+            let p2 = Polynomial::from_vec(fixture::<i64>(m, 5, 13, 6));
+            assert_eq!(p1.multiply(&p2), p1.toom3_mul_with(&p2, &thresholds));
+        }
+        let p3 = Polynomial::from_vec((0..600).map(|i| (i % 17) as i128 - 8).collect());
+        assert_eq!(p3.mul_algorithm(&p3, &MulThresholds::DEFAULT), MulAlgorithm::ToomCook3);
+        assert_eq!(p3.multiply(&p3), &p3 * &p3);
+    }
+
+    #[test]
+    fn test_toom3_2() {
+        // The largest coefficient of the product is 512 * 1024^2 = 2^29, which fits in i32,
+        // but the evaluation at -2 would not.
+        let p1 = Polynomial::from_vec(vec![1024i32; 512]);
+        assert_eq!(p1.mul_algorithm(&p1, &MulThresholds::DEFAULT), MulAlgorithm::ToomCook3);
+        assert_eq!(p1.multiply(&p1), &p1 * &p1);
+        // Alternating signs keep the product small while the NTT bound is too large for it,
+        // so the fallback has to be safe as well.
+        let alternating = |n:usize, c:i64| Polynomial::from_vec((0..n).map(|i| if i % 2 == 0 {c} else {-c}).collect());
+        let p2 = alternating(2048, 1 << 31);
+        let p3 = Polynomial::from_vec(vec![1i64 << 31; 2048]);
+        assert!(p2.ntt_mul(&p3).is_err());
+        assert_eq!(p2.mul_algorithm(&p3, &MulThresholds::DEFAULT), MulAlgorithm::Ntt);
+        assert_eq!(p2.multiply(&p3), &p2 * &p3);
+        let p4 = Polynomial::from_vec((0..2048).map(|i| if i % 2 == 0 {1i128 << 60} else {-1 << 60}).collect());
+        let p5 = Polynomial::from_vec(vec![1i128 << 60; 2048]);
+        assert!(p4.crt_mul(&p5).is_err());
+        assert_eq!(p4.multiply(&p5), &p4 * &p5);
+    }
+
+    #[test]
+    fn test_ntt_1() {
+        let mut values:Vec<u64> = (0..64).map(|i| (i * 12345) % 998_244_353).collect();
+        let original = values.clone();
+        ntt::<P998244353>(&mut values);
+        assert_ne!(values, original);
+        inverse_ntt::<P998244353>(&mut values);
+        assert_eq!(values, original);
+        // the twiddles of the longer transform above serve the shorter ones, which still agree with ring_fft
+        let mut long:Vec<u64> = (0..1024).map(|i| (i * 777) % 998_244_353).collect();
+        ntt::<P998244353>(&mut long);
+        for n in [1, 2, 8, 64] {
+            let mut values:Vec<u64> = (0..n).map(|i| (i * 12345 + 1) % 998_244_353).collect();
+            let mut ring:Vec<ModInt<P998244353>> = values.iter().map(|x| ModInt::new(*x)).collect();
+            ntt::<P998244353>(&mut values);
+            ring_fft(&mut ring).unwrap();
+            assert_eq!(values, ring.iter().map(|x| x.value()).collect::<Vec<u64>>());
+        }
+    }
+
+    #[test]
+    fn test_ring_fft_1() {
+        // modular integers, exact
+        let p1 = Polynomial::from_vec(fixture::<i64>(157, 7, 11, 5).into_iter().map(ModInt::<P998244353>::from_i64).collect());
+        let p2 = Polynomial::from_vec((0..90).map(|i| ModInt::<P998244353>::new(998_244_000 + i)).collect());
+        assert_eq!(p1.multiply(&p2), p1.ring_fft_mul(&p2).unwrap());
+        let thresholds = MulThresholds{ntt: 2, ..MulThresholds::DEFAULT};
+        assert_eq!(p1.mul_algorithm(&p2, &thresholds), MulAlgorithm::Ntt);
+        assert_eq!(p1.multiply(&p2), p1.mul_with(&p2, &thresholds));
+        // the same transform over the complex numbers agrees with fft
+        let x = complex_fixture(64);
+        let mut y = x.clone();
+        let mut z = x.clone();
+        ring_fft(&mut y).unwrap();
+        fft(&mut z);
+        assert!(y.iter().zip(z.iter()).all(|(a, b)| (a - b).norm() < 1e-9));
+        inverse_ring_fft(&mut y).unwrap();
+        assert!(x.iter().zip(y.iter()).all(|(a, b)| (a - b).norm() < 1e-9));
+        // field arithmetic
+        let a = ModInt::<P998244353>::new(12345);
+        assert_eq!(a / a, ModInt::new(1));
+        assert_eq!(a * a.inverse().unwrap(), ModInt::new(1));
+        assert_eq!((-a).value(), 998_244_353 - 12345);
+    }
+
+    #[test]
+    fn test_ntt_mul_1() {
+        let p1 = Polynomial::from_vec(fixture::<i64>(157, 7, 11, 5));
+        let p2 = Polynomial::from_vec(fixture::<i64>(90, 5, 13, 6));
+        assert_eq!(p1.multiply(&p2), p1.ntt_mul(&p2).unwrap());
+        assert_eq!(p1.multiply(&p2), p1.ntt_mul_with::<P998244353>(&p2).unwrap());
+        // big coefficients, where fft_mul would have lost the low digits
+        let p3 = Polynomial::from_vec(vec![-2_000_000_000i64, 1_000_000_007, 1_500_000_011]);
+        assert_eq!(p3.multiply(&p3), p3.ntt_mul(&p3).unwrap());
+        let p4 = Polynomial::from_vec(vec![3_000_000_000u64, 1, 2_000_000_000]);
+        assert_eq!(p4.multiply(&p4), p4.ntt_mul(&p4).unwrap());
+    }
+
+    #[test]
+    fn test_ntt_mul_2() {
+        let p1 = Polynomial::from_vec(vec![1i64 << 40, 1]);
+        let p2 = Polynomial::from_vec(vec![1i64 << 30, 1]);
+        assert_eq!(p1.ntt_mul(&p2), Err(PolynomialError::CoefficientOverflow));
+        assert_eq!(p1.ntt_mul_with::<P998244353>(&Polynomial::from_vec(vec![1, 1])), Err(PolynomialError::CoefficientOverflow));
+        // the bound is conservative, p * q falls back to an exact algorithm that has no bound to check
+        let p3 = Polynomial::from_vec(vec![3_000_000_000i64, 3_000_000_000]);
+        let p4 = Polynomial::from_vec(vec![3_000_000_000i64, -3_000_000_000]);
+        assert_eq!(p3.ntt_mul(&p4), Err(PolynomialError::CoefficientOverflow));
+        let thresholds = MulThresholds{ntt: 2, ..MulThresholds::DEFAULT};
+        assert_eq!(p3.mul_algorithm(&p4, &thresholds), MulAlgorithm::Ntt);
+        assert_eq!(p3.mul_with(&p4, &thresholds), Polynomial::from_vec(vec![9_000_000_000_000_000_000, 0, -9_000_000_000_000_000_000]));
+    }
+
+    #[test]
+    fn test_crt_mul_1() {
+        // one, two and three primes
+        for scale in [1i128, 1 << 40, 1 << 57] {
+            let p1 = Polynomial::from_vec(fixture::<i128>(157, 7, 11, 5).into_iter().enumerate().map(|(i, x)| x * scale + i as i128).collect());
+            let p2 = Polynomial::from_vec(fixture::<i128>(90, 5, 13, 6).into_iter().enumerate().map(|(i, x)| x * scale - i as i128).collect());
+            assert_eq!(p1.multiply(&p2), p1.crt_mul(&p2).unwrap());
+        }
+        let p3 = Polynomial::from_vec(vec![1i128 << 100, 1]);
+        assert_eq!(p3.crt_mul(&p3), Err(PolynomialError::CoefficientOverflow));
+    }
+
+    #[test]
+    fn test_fft_mul_exact_1() {
+        for (n, m, a_scale, b_scale) in [(1, 1, 3i64, 5i64), (157, 90, 1 << 35, 1 << 15), (2000, 1500, 1 << 20, 1 << 20)] {
+            let p1 = Polynomial::from_vec((0..n).map(|i| (((i * 7919) % 2001) - 1000) * (a_scale / 1000) + i).collect::<Vec<i64>>());
+            let p2 = Polynomial::from_vec((0..m).map(|i| (((i * 104729) % 2001) - 1000) * (b_scale / 1000) - i).collect::<Vec<i64>>());
+            let wide = |p:&Polynomial<i64>| Polynomial::from_vec(p.get_coeffs().iter().map(|x| *x as i128).collect());
+            let expected:Vec<i64> = wide(&p1).multiply(&wide(&p2)).get_coeffs().iter().map(|x| *x as i64).collect();
+            assert_eq!(Polynomial::from_vec(expected), p1.fft_mul_exact(&p2).unwrap());
+        }
+        // the extreme coefficients still split into limbs correctly
+        let p3 = Polynomial::from_vec(vec![i64::MIN, 0, 1]);
+        let p4 = Polynomial::from_vec(vec![1, 1]);
+        assert_eq!(p3.fft_mul_exact(&p4).unwrap(), Polynomial::from_vec(vec![i64::MIN, i64::MIN, 1, 1]));
+        let p5 = Polynomial::from_vec(vec![i64::MAX, 1]);
+        assert_eq!(p5.fft_mul_exact(&p4), Err(PolynomialError::CoefficientOverflow));
+    }
+
     #[test]
     fn test_divide_1() {
         let p1 = Polynomial::from_vec(vec![-1,1]);
@@ -175,6 +490,243 @@ mod test {
         assert_eq!(p1.divide_by(&p2).unwrap(), (Polynomial::from_vec(vec![1]), Polynomial::from_vec(vec![1,2,1])));
     }
 
+    // the remainder is self when the degree of self is too small
+    #[test]
+    fn test_divide_5() {
+        let p1 = Polynomial::from_vec(vec![1,2]);
+        let p2 = Polynomial::from_vec(vec![0,0,2]);
+        assert_eq!(p1.divide_by(&p2).unwrap(), (Polynomial::from_vec(vec![0]), Polynomial::from_vec(vec![1,2])));
+        // x^3 divided by 2x stops right away, with no leading zeros in the quotient
+        let p3 = Polynomial::from_vec(vec![0,0,0,1]);
+        let (q, r) = p3.divide_by(&Polynomial::from_vec(vec![0,2])).unwrap();
+        assert_eq!((q, r), (Polynomial::from_vec(vec![0]), Polynomial::from_vec(vec![0,0,0,1])));
+    }
+
+    #[test]
+    fn test_divide_6() {
+        // 1 - (1 / 49) * 49 is not 0 in f64, the leading term still has to go
+        let p1 = Polynomial::from_vec(vec![1.,2.,1.]);
+        let p2 = Polynomial::from_vec(vec![1.,49.]);
+        let (q, r) = p1.divide_by(&p2).unwrap();
+        assert_eq!(q.deg(), 1);
+        assert_eq!(r.deg(), 0);
+        assert_close(&(&q * &p2).plus(&r), &p1, 1e-12);
+        // same for a longer quotient, with leading coefficients 0.7 and 0.3
+        let p3 = Polynomial::from_vec(fixture::<f64>(40, 7, 11, 5).into_iter().map(|x| x * 0.1).chain([0.7]).collect());
+        let p4 = Polynomial::from_vec(vec![0.2, -0.1, 0.3]);
+        let (q, r) = p3.divide_by(&p4).unwrap();
+        assert_eq!(q.deg(), 38);
+        assert!(r.deg() < 2);
+        assert_close(&(&q * &p4).plus(&r), &p3, 1e-6);
+    }
+
+    #[test]
+    fn test_pseudo_divide_1() {
+        // 2^2 * (3x^2 + 2x + 1) = (6x + 1)(2x + 1) + 3
+        let p1 = Polynomial::from_vec(vec![1,2,3]);
+        let p2 = Polynomial::from_vec(vec![1,2]);
+        assert_eq!(p1.pseudo_divide(&p2).unwrap(), (Polynomial::from_vec(vec![1,6]), Polynomial::from_vec(vec![3])));
+        // the quotient coefficient of x^0 is 0, and lc(p)^2 still multiplies self
+        let p3 = Polynomial::from_vec(vec![5,0,0,7]);
+        let p4 = Polynomial::from_vec(vec![-1,0,3]);
+        let (q, r) = p3.pseudo_divide(&p4).unwrap();
+        assert!(r.deg() < p4.deg());
+        assert_eq!(q.multiply(&p4).plus(&r), p3.multiply(&Polynomial::from_vec(vec![9])));
+        assert_eq!(p2.pseudo_divide(&p1).unwrap(), (Polynomial::from_vec(vec![0]), Polynomial::from_vec(vec![1,2])));
+        assert_eq!(p1.pseudo_divide(&Polynomial::from_vec(vec![0])), None);
+    }
+
+    #[test]
+    fn test_exact_divide_1() {
+        let p1 = Polynomial::from_vec(vec![-2,1]);
+        let p2 = Polynomial::from_vec(vec![3,0,2]);
+        assert_eq!(p1.multiply(&p2).exact_divide(&p2), Ok(p1.copy()));
+        assert_eq!(p1.multiply(&p2).exact_divide(&p1), Ok(p2.copy()));
+        assert_eq!(p2.exact_divide(&p1), Err(PolynomialError::NotDivisible));
+        assert_eq!(p2.exact_divide(&Polynomial::from_vec(vec![0])), Err(PolynomialError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_div_rem_1() {
+        type M = ModInt<P998244353>;
+        let m = |n:usize, k:u64| Polynomial::from_vec((0..n as u64).map(|i| M::new((i * i * k + 3 * i + k) % 1009)).collect());
+        // big enough for Newton division, against long division
+        let (a, b) = (m(700, 7), m(300, 11));
+        let (q, r) = a.div_rem(&b).unwrap();
+        assert_eq!((q.copy(), r.copy()), a.divide_by(&b).unwrap());
+        assert!(r.deg() < b.deg());
+        assert_eq!(&a / &b, q);
+        assert_eq!(&a % &b, r);
+        // 1 / (1 - x) = 1 + x + x^2 + ...
+        let series = Polynomial::from_vec(vec![1., -1.]).series_inverse(6).unwrap();
+        assert_eq!(series, Polynomial::from_vec(vec![1.; 6]));
+        let inverse = b.series_inverse(200).unwrap();
+        let mut one = Array1::from_elem(200, M::new(0));
+        one[0] = M::new(1);
+        assert_eq!(b.multiply(&inverse).get_coeffs().slice(s![..200]), one);
+        assert_eq!(Polynomial::from_vec(vec![0., 1.]).series_inverse(3), None);
+    }
+
+    #[test]
+    fn test_div_rem_2() {
+        // x^7 - 1 = (x - 1)(x^6 + ... + 1)
+        let p1 = Polynomial::from_vec(vec![-1.,0.,0.,0.,0.,0.,0.,1.]);
+        let p2 = Polynomial::from_vec(vec![-1.,1.]);
+        assert_eq!(&p1 / &p2, Polynomial::from_vec(vec![1.; 7]));
+        assert_eq!(p1.copy() % p2.copy(), Polynomial::from_vec(vec![0.]));
+        // 3x^2 + 2x + 1 = 1.5 * 2x^2 + (2x + 1), where divide_by on integers stops at 1
+        let (q, r) = Polynomial::from_vec(vec![1.,2.,3.]).div_rem(&Polynomial::from_vec(vec![0.,0.,2.])).unwrap();
+        assert_eq!((q, r), (Polynomial::from_vec(vec![1.5]), Polynomial::from_vec(vec![1.,2.])));
+        assert_eq!(p2.div_rem(&p1).unwrap(), (Polynomial::from_vec(vec![0.]), p2.copy()));
+        assert_eq!(p1.div_rem(&Polynomial::from_vec(vec![0.])), None);
+        // f64 division through FFT products
+        let f = Polynomial::from_vec(fixture::<f64>(3000, 7, 11, 5));
+        let mut g_coeffs = fixture::<f64>(1000, 5, 13, 6);
+        g_coeffs[999] += 5000.;
+        let g = Polynomial::from_vec(g_coeffs);
+        let (q, r) = f.div_rem(&g).unwrap();
+        assert!(r.deg() < g.deg());
+        assert_close(&(&q * &g).plus(&r), &f, 1e-9);
+    }
+
+    #[test]
+    fn test_half_gcd_1() {
+        type M = ModInt<P998244353>;
+        let m = |n:usize, k:u64| Polynomial::from_vec((0..n as u64).map(|i| M::new((i * i * k + 3 * i + k) % 1009)).collect());
+        let common = m(40, 5);
+        let a = m(150, 7).multiply(&common);
+        let b = m(120, 11).multiply(&common);
+        let g = a.gcd(&b);
+        assert!(g.deg() >= common.deg());
+        for cutoff in [1, 4, 16, 1000] {
+            assert_eq!(a.fast_gcd_with_cutoff(&b, cutoff), g);
+        }
+        assert_eq!(b.fast_gcd(&a), g);
+        assert_eq!(a.fast_gcd(&m(1, 3)), m(1, 1).monic());
+        assert_eq!(a.fast_resultant(&b), M::new(0));
+        let (c, d) = (m(300, 7), m(200, 13));
+        assert_eq!(c.fast_resultant(&d), c.resultant(&d).unwrap());
+        assert_eq!(d.fast_resultant(&c), d.resultant(&c).unwrap());
+    }
+
+    #[test]
+    fn test_half_gcd_2() {
+        // Rounding errors in the matrix products used to keep the degrees from falling, so these never returned.
+        let f = Polynomial::from_vec(vec![-3f64,2.,0.,3.,4.,2.,-1.]);
+        let g = Polynomial::from_vec(vec![-4.,-1.,2.,2.,3.,0.,4.]);
+        for cutoff in [1, 2, 3, 128] {
+            assert_eq!(f.fast_gcd_with_cutoff(&g, cutoff), Polynomial::from_vec(vec![1.]));
+        }
+        let res = f.fast_resultant(&g);
+        assert!((res - f.resultant(&g).unwrap()).abs() < 1e-6 * res.abs(), "{}", res);
+        assert!((res + 406797203.).abs() < 1e-6 * res.abs());
+        // large enough for the recursion
+        let f2 = Polynomial::from_vec(fixture::<f64>(300, 7, 11, 5));
+        let g2 = Polynomial::from_vec(fixture::<f64>(290, 5, 13, 6));
+        assert_eq!(f2.fast_gcd(&g2).deg(), 0);
+        assert_eq!(f2.fast_gcd_with_cutoff(&g2, 4).deg(), 0);
+    }
+
+    #[test]
+    fn test_subresultant_1() {
+        // Knuth's example, whose Euclidean PRS over the integers blows up
+        let f = Polynomial::from_vec(vec![-5i64, 2, 8, -3, -3, 0, 1, 0, 1]);
+        let g = Polynomial::from_vec(vec![21, -9, -4, 0, 5, 0, 3]);
+        let prs = f.subresultant_prs(&g).unwrap();
+        assert_eq!(prs[2..], [
+            Polynomial::from_vec(vec![9, 0, -3, 0, 15]),
+            Polynomial::from_vec(vec![-245, 125, 65]),
+            Polynomial::from_vec(vec![-12300, 9326]),
+            Polynomial::from_vec(vec![260708])
+        ]);
+        assert_eq!(f.resultant(&g).unwrap(), 260708);
+        // the sequence starts with the higher degree, so the order does not change it
+        assert_eq!(g.subresultant_prs(&f).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_resultant_1() {
+        // the product of g over the roots 1, 2, -3 of f, with g = (x - 4)(x + 1)
+        let f = Polynomial::from_vec(vec![6, -7, 0, 1]);
+        let g = Polynomial::from_vec(vec![-4, -3, 1]);
+        assert_eq!(f.resultant(&g).unwrap(), 504);
+        assert_eq!(g.resultant(&f).unwrap(), 504);
+        // res(p, q) = (-1)^(deg p deg q) res(q, p)
+        let h = Polynomial::from_vec(vec![-2, 1]);
+        assert_eq!(f.resultant(&h).unwrap(), -h.resultant(&f).unwrap());
+        assert_eq!(h.resultant(&f).unwrap(), f.eval(2));
+        // a common root, constants and zero
+        assert_eq!(f.resultant(&Polynomial::from_vec(vec![-5, 4, 1])), Ok(0));
+        assert_eq!(f.resultant(&Polynomial::from_vec(vec![2])), Ok(8));
+        assert_eq!(f.resultant(&Polynomial::from_vec(vec![0])), Ok(0));
+    }
+
+    // The determinant of the Sylvester matrix of f and g, by fraction free Gaussian elimination.
+    fn sylvester_determinant(f:&[i128], g:&[i128]) -> i128 {
+        let (n, m) = (f.len() - 1, g.len() - 1);
+        let row = |p:&[i128], shift:usize| -> Vec<i128> {
+            let mut r = vec![0; n + m];
+            for (i, c) in p.iter().rev().enumerate() {
+                r[shift + i] = *c;
+            }
+            r
+        };
+        let mut a:Vec<Vec<i128>> = (0..m).map(|i| row(f, i)).chain((0..n).map(|i| row(g, i))).collect();
+        let (mut prev, mut sign) = (1, 1);
+        for k in 0..n + m {
+            let Some(pivot) = (k..n + m).find(|&i| a[i][k] != 0) else {return 0};
+            if pivot != k {
+                a.swap(pivot, k);
+                sign = -sign;
+            }
+            for i in k + 1..n + m {
+                for j in k + 1..n + m {
+                    a[i][j] = (a[k][k] * a[i][j] - a[i][k] * a[k][j]) / prev;
+                }
+            }
+            prev = a[k][k];
+        }
+        sign * prev
+    }
+
+    #[test]
+    fn test_resultant_2() {
+        let f:Vec<i128> = vec![-3, 2, 0, 3, 4, 2, -1];
+        let g:Vec<i128> = vec![-4, -1, 2, 2, 3, 0, 4];
+        assert_eq!(sylvester_determinant(&f, &g), -406797203);
+        assert_eq!(Polynomial::from_vec(f.clone()).resultant(&Polynomial::from_vec(g.clone())), Ok(-406797203));
+        // The result fits in i64, but the pseudo-remainders on the way do not.
+        let small = |p:&[i128]| Polynomial::from_vec(p.iter().map(|c| *c as i64).collect());
+        assert_eq!(small(&f).resultant(&small(&g)), Err(PolynomialError::CoefficientOverflow));
+        let floats = |p:&[i128]| Polynomial::from_vec(p.iter().map(|c| *c as f64).collect());
+        assert!((floats(&f).resultant(&floats(&g)).unwrap() + 406797203.).abs() < 1e-3);
+        // degrees 6 to 9, i64 gives either the right value or an error
+        for n in 6..10 {
+            for m in 6..=n {
+                let f:Vec<i128> = fixture(n + 1, 7, 11, 5);
+                let g:Vec<i128> = fixture(m + 1, 5, 13, 6);
+                if f[n] == 0 || g[m] == 0 {
+                    continue
+                }
+                let res = sylvester_determinant(&f, &g);
+                assert_eq!(Polynomial::from_vec(f.clone()).resultant(&Polynomial::from_vec(g.clone())), Ok(res));
+                let (f, g) = (small(&f), small(&g));
+                assert!(matches!(f.resultant(&g), Err(PolynomialError::CoefficientOverflow)) || f.resultant(&g) == Ok(res as i64));
+            }
+        }
+    }
+
+    #[test]
+    fn test_discriminant_1() {
+        // b^2 - 4ac
+        assert_eq!(Polynomial::from_vec(vec![1, 3, 2]).discriminant().unwrap(), 1);
+        assert_eq!(Polynomial::from_vec(vec![1, 2, 1]).discriminant().unwrap(), 0);
+        // -4p^3 - 27q^2 for x^3 + px + q
+        assert_eq!(Polynomial::from_vec(vec![1, -1, 0, 1]).discriminant().unwrap(), -23);
+        assert_eq!(Polynomial::from_vec(vec![-3, 2]).discriminant().unwrap(), 1);
+        assert_eq!(Polynomial::from_vec(vec![1., 3., 2.]).discriminant().unwrap(), 1.);
+    }
+
     #[test]
     fn test_fft_1() {
         let p1 = Polynomial::from_vec(vec![-1.,1.]);
@@ -200,6 +752,416 @@ mod test {
         assert_eq!(p2.pow(5), fft);
     }
 
+    #[test]
+    fn test_fft_4() {
+        // big enough that the transform has many butterfly stages
+        let p1 = Polynomial::from_vec(fixture::<f64>(300, 7, 11, 5));
+        let p2 = Polynomial::from_vec(fixture::<f64>(213, 5, 13, 6));
+        assert_close(&p1.multiply(&p2), &p1.fft_mul(&p2, 5), 1e-4);
+        assert_close(&p1.multiply(&p2), &p1.fft_mul_threaded(&p2, 5), 1e-4);
+    }
+
+    #[test]
+    fn test_fft_plan_1() {
+        // one plan, reused for several products that fit in it
+        let mut plan = FftPlan::new(32);
+        let p1 = Polynomial::from_vec(vec![-1.,1.]);
+        let p2 = Polynomial::from_vec(vec![1.,1.,1.,1.,1.,1.,1.]);
+        let p3 = Polynomial::from_vec(vec![1.,2.,3.]).pow(5);
+        assert_close(&p1.multiply(&p2), &p1.fft_mul_with_plan(&p2, 5, &mut plan), 1e-4);
+        assert_close(&p3.multiply(&p2), &p3.fft_mul_with_plan(&p2, 5, &mut plan), 1e-4);
+        assert_close(&p3.multiply(&p1), &p3.fft_mul_threaded_with_plan(&p1, 5, &mut plan), 1e-4);
+    }
+
+    #[test]
+    fn test_fft_plan_2() {
+        // the thread's cache keeps only the most recently used lengths, Bluestein ones included
+        for len in 1..=40 {
+            FftPlan::<f64>::with_cached(len, |plan| assert_eq!(plan.len(), len));
+            FftPlan::<f64>::with_cached(1, |_| ());
+        }
+        f64::with_plan_cache(|plans| {
+            assert_eq!(plans.len(), PLAN_CACHE_CAPACITY);
+            assert!(plans.contains(1));
+            assert!(plans.contains(40));
+            assert!(!plans.contains(2));
+        });
+    }
+
+    #[test]
+    fn test_fft_real_1() {
+        // fft_mul and fft_mul_threaded run the same half length real transforms
+        for (n, m) in [(2, 2), (17, 40), (300, 301), (1000, 77)] {
+            let p1 = Polynomial::from_vec(fixture::<f64>(n, 7, 11, 5));
+            let p2 = Polynomial::from_vec(fixture::<f64>(m, 5, 13, 6).into_iter().map(|x| x - 0.25).collect());
+            let product = p1.fft_mul(&p2, 8);
+            assert!(product == p1.fft_mul_threaded(&p2, 8));
+            assert_close(&p1.multiply(&p2), &product, 1e-6);
+        }
+        // odd length plans have to use full complex transforms
+        let mut plan = FftPlan::new(45);
+        let p1 = Polynomial::from_vec(vec![1., -2., 3., 0.5]);
+        let p2 = Polynomial::from_vec((0..30).map(|i| i as f64).collect());
+        assert_close(&p1.multiply(&p2), &p1.fft_mul_with_plan(&p2, 8, &mut plan), 1e-6);
+        assert_close(&p1.multiply(&p2), &p1.fft_mul_threaded_with_plan(&p2, 8, &mut plan), 1e-6);
+    }
+
+    #[test]
+    fn test_fft_complex_1() {
+        for (n, m) in [(2, 3), (17, 40), (300, 301)] {
+            let p1 = Polynomial::from_vec(complex_fixture(n));
+            let p2 = Polynomial::from_vec(fixture::<f64>(m, 5, 13, 6).into_iter().enumerate().map(|(i, re)| Complex64::new(re, -(i as f64))).collect());
+            let exact = p1.multiply(&p2);
+            assert!(exact == p1.fft_mul(&p2, RoundingPolicy::RoundToInteger));
+            assert!(exact == p1.fft_mul_parallel(&p2, 6, Parallelism::Threads(2)));
+            // get_value_repr and from_value_repr undo each other
+            let values = exact.get_value_repr();
+            let back = Polynomial::<Complex64>::from_value_repr(values.view());
+            assert!(exact.get_coeffs().iter().zip(back.get_coeffs().iter()).all(|(a, b)| (a - b).norm() < 1e-6));
+            // p * q uses the FFT from thresholds.fft on
+            let algorithm = if n >= 256 {MulAlgorithm::Fft} else {MulAlgorithm::Schoolbook};
+            assert_eq!(p1.mul_algorithm(&p2, &MulThresholds::DEFAULT), algorithm);
+            let product = &p1 * &p2;
+            assert_eq!(product.len(), exact.len());
+            assert!(exact.get_coeffs().iter().zip(product.get_coeffs().iter()).all(|(a, b)| (a - b).norm() < 1e-6));
+        }
+        // single precision
+        let p1 = Polynomial::from_vec(fixture::<f32>(40, 7, 11, 5).into_iter().map(|re| Complex32::new(re, 1.)).collect());
+        let p2 = Polynomial::from_vec((0..25).map(|i| Complex32::new(0.5, ((i * 3) % 5) as f32)).collect());
+        assert!(p1.multiply(&p2) == p1.fft_mul(&p2, 1));
+        let thresholds = MulThresholds{fft: 2, ..MulThresholds::DEFAULT};
+        assert_eq!(p1.mul_algorithm(&p2, &thresholds), MulAlgorithm::Fft);
+        assert!(p1.multiply(&p2).get_coeffs().iter().zip(p1.mul_with(&p2, &thresholds).get_coeffs().iter()).all(|(a, b)| (a - b).norm() < 1e-3));
+    }
+
+    #[test]
+    fn test_fft_f32_1() {
+        for (n, m) in [(2, 2), (17, 40), (300, 301)] {
+            let p1 = Polynomial::from_vec(fixture::<f32>(n, 7, 11, 5));
+            let p2 = Polynomial::from_vec(fixture::<f32>(m, 5, 13, 6));
+            let exact = p1.multiply(&p2);
+            assert!(exact == p1.fft_mul(&p2, RoundingPolicy::RoundToInteger));
+            assert!(exact == p1.fft_mul_threaded(&p2, RoundingPolicy::RoundToInteger));
+            let unrounded = p1.fft_mul(&p2, RoundingPolicy::None);
+            assert!(exact.get_coeffs().iter().zip(unrounded.get_coeffs().iter()).all(|(a, b)| (a - b).abs() < 1e-2));
+            // p * q uses the FFT from thresholds.fft on
+            let algorithm = if n >= 256 {MulAlgorithm::Fft} else {MulAlgorithm::Schoolbook};
+            assert_eq!(p1.mul_algorithm(&p2, &MulThresholds::DEFAULT), algorithm);
+            let product = &p1 * &p2;
+            assert_eq!(product.len(), exact.len());
+            assert!(exact.get_coeffs().iter().zip(product.get_coeffs().iter()).all(|(a, b)| (a - b).abs() < 1e-2));
+        }
+        let p1 = Polynomial::from_vec(vec![1f32, -2., 0., 3., 0.5]);
+        let values = p1.get_value_repr_at(7);
+        let back = Polynomial::<f32>::from_value_repr(values.view());
+        assert!(p1.get_coeffs().iter().zip(back.get_coeffs().iter()).all(|(a, b)| (a - b).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_fft_parallel_1() {
+        // long enough to be split, with radix 3 and 5 stages and an uneven number of threads
+        for n in [1 << 15, 61440, 20011] {
+            let x = complex_fixture(n);
+            let plan = FftPlan::new(n);
+            let mut serial = x.clone();
+            plan.forward(&mut serial);
+            for threads in [2, 3, 4, 7, 64] {
+                let mut parallel = x.clone();
+                plan.forward_parallel(&mut parallel, Parallelism::Threads(threads));
+                assert!(parallel == serial);
+                plan.inverse_parallel(&mut parallel, Parallelism::Threads(threads));
+                assert!(x.iter().zip(parallel.iter()).all(|(a, b)| (a - b).norm() < 1e-9));
+            }
+        }
+        let p1 = Polynomial::from_vec(fixture::<f64>(20000, 7, 11, 5));
+        let p2 = Polynomial::from_vec(fixture::<f64>(9000, 5, 13, 6));
+        assert!(p1.fft_mul(&p2, 5) == p1.fft_mul_parallel(&p2, 5, Parallelism::Threads(3)));
+        assert!(p1.fft_mul(&p2, 5) == p1.fft_mul_parallel(&p2, 5, Parallelism::Threads(16)));
+        assert!(p1.fft_mul(&p2, 5) == p1.fft_mul_parallel(&p2, 5, Parallelism::Available));
+    }
+
+    #[test]
+    fn test_convolve_1() {
+        // same values as numpy.convolve and numpy.correlate
+        let a = array![1., 2., 3.];
+        let b = array![0., 1., 0.5];
+        assert_eq!(convolve(a.view(), b.view(), ConvolveMode::Full), array![0., 1., 2.5, 4., 1.5]);
+        assert_eq!(convolve(a.view(), b.view(), ConvolveMode::Same), array![1., 2.5, 4.]);
+        assert_eq!(convolve(a.view(), b.view(), ConvolveMode::Valid), array![2.5]);
+        // the trailing zero stays
+        assert_eq!(correlate(a.view(), b.view(), ConvolveMode::Full), array![0.5, 2., 3.5, 3., 0.]);
+        assert_eq!(correlate(a.view(), b.view(), ConvolveMode::Valid), array![3.5]);
+        assert_eq!(convolve(a.view(), array![1., 1.].view(), ConvolveMode::Same), array![1., 3., 5.]);
+    }
+
+    #[test]
+    fn test_convolve_2() {
+        // long enough for the FFT, compared against the direct sum
+        let a = Array1::from(fixture::<f64>(1000, 7, 11, 5));
+        let b = Array1::from(fixture::<f64>(601, 5, 13, 6));
+        let full = convolve(a.view(), b.view(), ConvolveMode::Full);
+        assert_eq!(full.len(), 1600);
+        for k in [0, 1, 600, 999, 1599] {
+            let expected:f64 = (0..1000).filter(|j| k >= *j && k - j < 601).map(|j| a[j] * b[k - j]).sum();
+            assert!((full[k] - expected).abs() < 1e-6);
+        }
+        let same = convolve(a.view(), b.view(), ConvolveMode::Same);
+        assert_eq!(same.len(), 1000);
+        assert!((same[0] - full[300]).abs() < 1e-9);
+        let valid = convolve(b.view(), a.view(), ConvolveMode::Valid);
+        assert_eq!(valid.len(), 400);
+        assert!((valid[0] - full[600]).abs() < 1e-9);
+        // strided views, and f32
+        let every_other = correlate(a.slice(s![..;2]), b.slice(s![..;2]), ConvolveMode::Full);
+        let reversed:Array1<f64> = b.iter().step_by(2).rev().copied().collect();
+        let expected = convolve(a.slice(s![..;2]).to_owned().view(), reversed.view(), ConvolveMode::Full);
+        assert!(every_other.iter().zip(expected.iter()).all(|(x, y)| (x - y).abs() < 1e-6));
+        let a32:Array1<f32> = a.iter().map(|x| *x as f32).collect();
+        let b32:Array1<f32> = b.iter().map(|x| *x as f32).collect();
+        let full32 = convolve(a32.view(), b32.view(), ConvolveMode::Full);
+        assert!(full.iter().zip(full32.iter()).all(|(x, y)| (*x as f32 - y).abs() < 1e-1));
+    }
+
+    #[test]
+    fn test_streaming_convolver_1() {
+        let filter = Polynomial::from_vec(fixture::<f64>(37, 5, 13, 6));
+        let signal = Array1::from(fixture::<f64>(5000, 7, 11, 5));
+        let expected = convolve(signal.view(), filter.get_coeffs_view(), ConvolveMode::Full);
+        for method in [StreamingMethod::OverlapAdd, StreamingMethod::OverlapSave] {
+            let mut convolver = StreamingConvolver::with_block_len(&filter, method, 100);
+            assert!(convolver.block_len() >= 100);
+            // twice, to check that finish resets the convolver
+            for _ in 0..2 {
+                let mut out = Vec::new();
+                // chunks that do not line up with the blocks
+                for chunk in signal.as_slice().unwrap().chunks(333) {
+                    out.extend(convolver.process(ArrayView1::from(chunk)));
+                }
+                out.extend(convolver.finish());
+                assert_eq!(out.len(), expected.len());
+                assert!(out.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-8));
+            }
+        }
+        // a one tap filter just scales
+        let mut convolver = StreamingConvolver::new(&Polynomial::from_vec(vec![2f32]), StreamingMethod::OverlapSave);
+        let mut out = convolver.process(array![1f32, 2., 3.].view()).to_vec();
+        out.extend(convolver.finish());
+        assert_eq!(out, vec![2., 4., 6.]);
+    }
+
+    #[test]
+    fn test_prepared_polynomial_1() {
+        let kernel = Polynomial::from_vec(fixture::<f64>(50, 5, 13, 6));
+        let prepared = PreparedPolynomial::new(&kernel, 200);
+        assert!(prepared.max_operand_len() >= 200);
+        let others:Vec<Polynomial<f64>> = (1..40).map(|n| {
+            Polynomial::from_vec((0..n * 5).map(|i| ((i * 7 + n) % 11) as f64 - 5.).collect())
+        }).collect();
+        for other in others.iter() {
+            assert_eq!(kernel.multiply(other), prepared.mul(other, RoundingPolicy::RoundToInteger));
+        }
+        let products = prepared.mul_many(&others, RoundingPolicy::RoundToInteger, Parallelism::Threads(3));
+        assert_eq!(products.len(), others.len());
+        assert!(products.iter().zip(others.iter()).all(|(p, other)| *p == prepared.mul(other, RoundingPolicy::RoundToInteger)));
+        // too long for the prepared length, and constants
+        let long = Polynomial::from_vec((0..1000).map(|i| ((i * 3) % 7) as f64).collect());
+        assert_eq!(kernel.multiply(&long), prepared.mul(&long, RoundingPolicy::RoundToInteger));
+        assert_eq!(kernel.multiply(&Polynomial::from_vec(vec![2.])), prepared.mul(&Polynomial::from_vec(vec![2.]), 5));
+        // the stored transform is the value representation
+        let values = kernel.get_value_repr_at(prepared.len());
+        assert!(values.iter().zip(prepared.get_value_repr().iter()).all(|(a, b)| (a - b).norm() < 1e-9));
+    }
+
+    #[test]
+    fn test_square_1() {
+        let p = Polynomial::from_vec((0..400).map(|i| (i % 7) as i64 - 3).collect());
+        assert_eq!(p.square_schoolbook(), p.multiply(&p));
+        let all_schoolbook = MulThresholds{karatsuba: usize::MAX, toom3: usize::MAX, fft: usize::MAX, ntt: usize::MAX};
+        assert_eq!(p.square_with(&all_schoolbook), p.multiply(&p));
+        let all_ntt = MulThresholds{ntt: 1, ..MulThresholds::DEFAULT};
+        assert_eq!(p.square_with(&all_ntt), p.multiply(&p));
+        let f = Polynomial::from_vec((0..400).map(|i| (i % 5) as f64 - 2.).collect());
+        let all_fft = MulThresholds{fft: 1, ..MulThresholds::DEFAULT};
+        assert_close(&f.square_with(&all_fft), &f.multiply(&f), 1e-6);
+        let m = Polynomial::from_vec((0..300).map(|i| ModInt::<P998244353>::new(i * 31 + 7)).collect());
+        assert_eq!(m.square_with(&all_ntt), m.multiply(&m));
+        // (1+x)^n mod 998244353 through squaring, against binomials built one factor at a time
+        let one_plus_x = Polynomial::from_vec(vec![ModInt::<P998244353>::new(1); 2]);
+        let mut expected = Polynomial::from_vec(vec![ModInt::<P998244353>::new(1)]);
+        for _ in 0..37 {
+            expected = expected.multiply(&one_plus_x);
+        }
+        assert_eq!(one_plus_x.pow(37), expected);
+        assert_eq!(one_plus_x.pow(5000).get_coeffs()[2].value(), 5000 * 4999 / 2);
+    }
+
+    #[test]
+    fn test_gcd_1() {
+        type M = ModInt<P998244353>;
+        let m = |v:Vec<i64>| Polynomial::from_vec(v.into_iter().map(M::from_i64).collect());
+        // (x - 1)(x + 2)(x + 5) and (x - 1)(x + 5)(x - 7)
+        let a = m(vec![-1, 1]).multiply(&m(vec![2, 1])).multiply(&m(vec![5, 1]));
+        let b = m(vec![-1, 1]).multiply(&m(vec![5, 1])).multiply(&m(vec![-7, 1]));
+        let expected = m(vec![-1, 1]).multiply(&m(vec![5, 1]));
+        assert_eq!(a.gcd(&b), expected);
+        let (g, s, t) = a.xgcd(&b);
+        assert_eq!(g, expected);
+        assert_eq!(s.multiply(&a).plus(&t.multiply(&b)), g);
+        assert_eq!(a.lcm(&b), expected.multiply(&m(vec![2, 1])).multiply(&m(vec![-7, 1])));
+        // coprime, and with zero
+        assert_eq!(m(vec![2, 1]).gcd(&m(vec![3, 1])), m(vec![1]));
+        assert_eq!(a.gcd(&m(vec![0])), a.monic());
+        assert_eq!(m(vec![0]).gcd(&m(vec![0])), m(vec![0]));
+    }
+
+    #[test]
+    fn test_gcd_2() {
+        // (x - 0.1)(x - 0.2) and (x - 0.1)(x + 0.3)
+        let a = Polynomial::from_vec(vec![-0.1, 1.]).multiply(&Polynomial::from_vec(vec![-0.2, 1.]));
+        let b = Polynomial::from_vec(vec![-0.1, 1.]).multiply(&Polynomial::from_vec(vec![0.3, 1.]));
+        assert_close(&a.gcd_with_tolerance(&b, 1e-9), &Polynomial::from_vec(vec![-0.1, 1.]), 1e-9);
+        let (g, s, t) = a.xgcd_with_tolerance(&b, 1e-9);
+        assert_close(&s.multiply(&a).plus(&t.multiply(&b)), &g, 1e-9);
+        // exact when the arithmetic is
+        assert_eq!(Polynomial::from_vec(vec![1., 2., 1.]).gcd(&Polynomial::from_vec(vec![1., 1.])), Polynomial::from_vec(vec![1., 1.]));
+    }
+
+    #[test]
+    fn test_rounding_1() {
+        // truncating would give -0.99999 and 2
+        assert_eq!(RoundingPolicy::RoundToDecimals(5).apply(-0.9999999), -1.);
+        assert_eq!(RoundingPolicy::RoundToDecimals(5).apply(2.9999999), 3.);
+        assert_eq!(RoundingPolicy::RoundToDecimals(2).apply(-1.234), -1.23);
+        assert_eq!(RoundingPolicy::RoundToInteger.apply(-2.5000001), -3.);
+        assert_eq!(RoundingPolicy::SnapNearZero(1e-9).apply(-3e-12), 0.);
+        assert_eq!(RoundingPolicy::SnapNearZero(1e-9).apply(0.1), 0.1);
+        assert_eq!(RoundingPolicy::None.apply(0.1), 0.1);
+        assert_eq!(RoundingPolicy::from(3), RoundingPolicy::RoundToDecimals(3));
+    }
+
+    #[test]
+    fn test_rounding_2() {
+        let p1 = Polynomial::from_vec(fixture::<f64>(300, 7, 11, 5));
+        let p2 = Polynomial::from_vec(fixture::<f64>(200, 5, 13, 6));
+        let exact = p1.multiply(&p2);
+        assert_eq!(exact, p1.fft_mul(&p2, RoundingPolicy::RoundToInteger));
+        assert_eq!(exact, p1.fft_mul_threaded(&p2, RoundingPolicy::RoundToDecimals(6)));
+        assert_eq!(exact, p1.fft_mul(&p2, 6));
+        let unrounded = p1.fft_mul(&p2, RoundingPolicy::None);
+        assert_close(&exact, &unrounded, 1e-9);
+        let snapped = p1.fft_mul(&p2, RoundingPolicy::SnapNearZero(1e-9));
+        assert!(snapped.get_coeffs().iter().zip(exact.get_coeffs().iter()).all(|(a, b)| (*b == 0.) == (*a == 0.)));
+    }
+
+    #[test]
+    fn test_fft_error_1() {
+        for (n, m, c) in [(10, 7, 3), (500, 300, 1000), (5000, 4000, 1 << 12)] {
+            let p1 = Polynomial::from_vec((0..n).map(|i| ((i * 7919) % (2 * c + 1)) as f64 - c as f64).collect());
+            let p2 = Polynomial::from_vec((0..m).map(|i| ((i * 104729) % (2 * c + 1)) as f64 - c as f64).collect());
+            let (unrounded, bound) = p1.fft_mul_with_error(&p2, RoundingPolicy::None);
+            let exact = p1.multiply(&p2);
+            let max_error = exact.get_coeffs().iter().zip(unrounded.get_coeffs().iter())
+                .map(|(a, b)| (a - b).abs()).fold(0., f64::max);
+            assert!(max_error <= bound);
+            assert!(bound < 0.5);
+            assert_eq!(exact, p1.fft_mul(&p2, RoundingPolicy::RoundToInteger));
+        }
+        // coefficients around 2^30 are too big to round safely
+        let p1 = Polynomial::from_vec((0..3000).map(|i| ((i * 7919) % 2001) as f64 * 1e6).collect());
+        assert!(p1.fft_mul_error_bound(&p1) > 0.5);
+    }
+
+    #[test]
+    fn test_fft_error_2() {
+        // the same bound for f32, with f32's epsilon
+        for (n, m, c) in [(10, 7, 3), (300, 200, 5)] {
+            let p1 = Polynomial::from_vec((0..n).map(|i| ((i * 7919) % (2 * c + 1)) as f32 - c as f32).collect());
+            let p2 = Polynomial::from_vec((0..m).map(|i| ((i * 104729) % (2 * c + 1)) as f32 - c as f32).collect());
+            let (unrounded, bound) = p1.fft_mul_with_error(&p2, RoundingPolicy::None);
+            let exact = p1.multiply(&p2);
+            let max_error = exact.get_coeffs().iter().zip(unrounded.get_coeffs().iter())
+                .map(|(a, b)| (a - b).abs()).fold(0., f32::max);
+            assert!(max_error <= bound);
+            assert!(bound < 0.5);
+            assert_eq!(exact, p1.fft_mul(&p2, RoundingPolicy::RoundToInteger));
+        }
+        // coefficients that f64 multiplies safely, but f32 does not
+        let p1 = Polynomial::from_vec((0..1000).map(|i| ((i * 7919) % 2001) as f64 - 1000.).collect());
+        let p2 = Polynomial::from_vec(p1.get_coeffs().iter().map(|x| *x as f32).collect());
+        assert!(p1.fft_mul_error_bound(&p1) < 0.5);
+        assert!(p2.fft_mul_error_bound(&p2) > 0.5);
+    }
+
+    #[test]
+    fn value_repr_1() {
+        // the value representation is p evaluated at the powers of w = e^(2*pi*i/n)
+        let p1 = Polynomial::from_vec(vec![1., 2., 3., 4., 5., 6., 7., 8.]);
+        let values = p1.get_value_repr();
+        let n = p1.len();
+        for (k, v) in values.iter().enumerate() {
+            let w = Complex64::from_polar(1., 2. * std::f64::consts::PI * (k as f64) / (n as f64));
+            let expected = p1.get_coeffs().iter().rev().fold(Complex64::new(0., 0.), |acc, c| acc * w + c);
+            assert!((v - expected).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn value_repr_2() {
+        // mixed radix lengths
+        for n in [3usize, 5, 6, 12, 25, 30, 45, 60] {
+            let p1 = Polynomial::from_vec(fixture::<f64>(n, 7, 11, 5).into_iter().map(|x| x + 0.5).collect());
+            let values = p1.get_value_repr();
+            for (k, v) in values.iter().enumerate() {
+                let w = Complex64::from_polar(1., 2. * std::f64::consts::PI * (k as f64) / (n as f64));
+                let expected = p1.get_coeffs().iter().rev().fold(Complex64::new(0., 0.), |acc, c| acc * w + c);
+                assert!((v - expected).norm() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fft_any_length_1() {
+        // prime lengths and lengths with a large prime factor go through Bluestein
+        for n in [1usize, 7, 14, 97, 210, 1009] {
+            let x = complex_fixture(n);
+            let mut y = x.clone();
+            fft(&mut y);
+            for (k, v) in y.iter().enumerate().step_by(n / 7 + 1) {
+                let expected:Complex64 = x.iter().enumerate().map(|(j, c)| {
+                    c * Complex64::from_polar(1., 2. * std::f64::consts::PI * ((j * k) % n) as f64 / (n as f64))
+                }).sum();
+                assert!((v - expected).norm() < 1e-8 * (n as f64));
+            }
+            inverse_fft(&mut y);
+            for (a, b) in x.iter().zip(y.iter()) {
+                assert!((a - b).norm() < 1e-9);
+            }
+        }
+        // more roots of unity than coefficients, and fewer
+        let p1 = Polynomial::from_vec(vec![1., -2., 0., 3., 0.5]);
+        for n in [3usize, 7, 11] {
+            let values = p1.get_value_repr_at(n);
+            assert_eq!(values.len(), n);
+            for (k, v) in values.iter().enumerate() {
+                let w = Complex64::from_polar(1., 2. * std::f64::consts::PI * (k as f64) / (n as f64));
+                let expected = p1.get_coeffs().iter().rev().fold(Complex64::new(0., 0.), |acc, c| acc * w + c);
+                assert!((v - expected).norm() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fft_padding_1() {
+        assert_eq!(FftPlan::padded_len(1024), 1024);
+        assert!(FftPlan::padded_len(1025) < 1300);
+        // products whose padded length is not a power of 2
+        for (n, m) in [(9, 9), (20, 21), (33, 40), (301, 50)] {
+            let p1 = Polynomial::from_vec(fixture::<f64>(n, 7, 11, 5));
+            let p2 = Polynomial::from_vec(fixture::<f64>(m, 5, 13, 6));
+            assert_close(&p1.multiply(&p2), &p1.fft_mul(&p2, 5), 1e-4);
+        }
+    }
+
     #[test]
     fn pow_1() {
         let p1 = Polynomial::from_vec(vec![-1.,1.]).pow(4);
@@ -211,6 +1173,8 @@ mod test {
     fn pow_2() {
         let p1 = Polynomial::from_vec(vec![-1,1]); 
         assert_eq!(Polynomial::from_vec(vec![-1, 5,-10, 10, -5, 1]), p1.pow(5));
+        assert_eq!(Polynomial::from_vec(vec![1]), p1.pow(0));
+        assert_eq!(Polynomial::from_vec(vec![1]), Polynomial::from_vec(vec![0]).pow(0));
     }
 
     #[test]
@@ -1,10 +1,13 @@
 use itertools::{EitherOrBoth::*, Itertools};
 use ndarray::{Array1, ArrayView1, s};
-use std::f64::consts::PI;
+use std::f64::consts::{PI, SQRT_2};
 use std::thread;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use num_complex::*;
-use num_traits::{Num, Zero, One};
-use std::{cmp::PartialEq, fmt::Display, ops::{Add, Sub, Mul}};
+use num_traits::{Float, Num, NumAssign, Zero, One, Signed};
+use std::{cmp::PartialEq, fmt::Display, marker::PhantomData, ops::{Add, Sub, Mul, Div, Rem, Neg}};
 
 //-------------------------------------------------------------------------------------------------------------
 // Abstract Implementation of polynomials
@@ -15,6 +18,35 @@ pub struct Polynomial<T>
     coeffs: Array1<T>
 }
 
+/// Why an operation that can fail did not return a polynomial.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolynomialError {
+    /// The coefficients of the result could be too large to represent exactly.
+    CoefficientOverflow,
+    /// The transform would need more points than the coefficient ring has roots of unity for.
+    TransformTooLong,
+    /// Floating point transforms could not be guaranteed to round to the exact result.
+    PrecisionLoss,
+    /// The divisor was the zero polynomial.
+    DivisionByZero,
+    /// The divisor does not divide the polynomial, the remainder is not 0.
+    NotDivisible
+}
+
+impl std::fmt::Display for PolynomialError {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PolynomialError::CoefficientOverflow => write!(f, "The coefficients of the result could be too large to represent exactly."),
+            PolynomialError::TransformTooLong => write!(f, "The transform needs more roots of unity than the coefficient ring has."),
+            PolynomialError::PrecisionLoss => write!(f, "The floating point transforms are not precise enough for an exact result."),
+            PolynomialError::DivisionByZero => write!(f, "Division by the zero polynomial."),
+            PolynomialError::NotDivisible => write!(f, "The divisor does not divide the polynomial exactly.")
+        }
+    }
+}
+
+impl std::error::Error for PolynomialError {}
+
 impl <T> Polynomial<T> 
     where T: Num + Clone + Copy + Display
 {
@@ -34,7 +66,7 @@ impl <T> Polynomial<T>
     /// 
     /// returns a polynomial.
     pub fn no_leading_zeros(mut c:Vec<T>) -> Polynomial<T> {
-        if c.len() == 0 {
+        if c.is_empty() {
             panic!("Cannot generate polynomial from empty vec.")
         }
         while c.len() > 1 {
@@ -80,6 +112,8 @@ impl <T> Polynomial<T>
         }
     }
 
+    // a polynomial always has at least one coefficient, so there is no is_empty
+    #[allow(clippy::len_without_is_empty)]
     #[inline]
     pub fn len(&self) -> usize {
         self.coeffs.len()
@@ -91,7 +125,7 @@ impl <T> Polynomial<T>
     }
 
     #[inline]
-    pub fn get_coeffs_view(&self) -> ArrayView1<T> {
+    pub fn get_coeffs_view(&self) -> ArrayView1<'_, T> {
         self.coeffs.view()
     }
 
@@ -103,7 +137,7 @@ impl <T> Polynomial<T>
     /// Polynomial of degree n with constant coefficient c
     pub fn const_coef(c:T, n:usize) -> Polynomial<T> {
         if c == T::zero() {
-            return Polynomial::zero()
+            Polynomial::zero()
         } else {
             match n {
                 0 => Polynomial{coeffs: Array1::from_elem(1, T::zero())},
@@ -117,7 +151,7 @@ impl <T> Polynomial<T>
     /// n = 2, c = 2 returns 2x^2
     pub fn basis(c:T, n:usize) -> Polynomial<T> {
         if c == T::zero() {
-            return Polynomial::zero()
+            Polynomial::zero()
         } else {
             match n {
                 0 => Polynomial{coeffs: Array1::from_elem(1, c)},
@@ -168,7 +202,31 @@ impl <T> Polynomial<T>
         Polynomial::no_leading_zeros(new_poly)
     }
 
-    /// Long Division
+    /// Karatsuba multiplication, O(n^1.585). Exact as long as the intermediate values fit in T, so this is the
+    /// fast option for integer coefficients. For fixed-width integers they may not: every level of the recursion
+    /// multiplies sums of two halves, so it needs about one more bit per level than the product itself,
+    /// and overflows where multiply would not. p * q checks for this and multiplies the schoolbook way instead.
+    /// Operands shorter than the global MulThresholds::karatsuba are multiplied the schoolbook way.
+    #[inline]
+    pub fn karatsuba_mul(&self, p:&Polynomial<T>) -> Polynomial<T> {
+        self.karatsuba_mul_with_cutoff(p, MulThresholds::global().karatsuba)
+    }
+
+    /// Karatsuba multiplication that switches to schoolbook once the shorter operand has fewer than cutoff coefficients.
+    pub fn karatsuba_mul_with_cutoff(&self, p:&Polynomial<T>, cutoff:usize) -> Polynomial<T> {
+        let a = self.coeffs.as_slice().unwrap();
+        let b = p.coeffs.as_slice().unwrap();
+        Polynomial::no_leading_zeros(karatsuba(a, b, cutoff.max(2)))
+    }
+
+    /// Long Division. Returns (quotient, remainder) with self = quotient * p + remainder, or None if p is 0.
+    /// 
+    /// Every step divides the leading coefficient of the remainder by that of p with T's own division, so
+    /// over a field (or when p's leading coefficient is 1 or -1) deg(remainder) < deg(p). Over the integers
+    /// the division truncates, and the steps stop as soon as one can not lower the degree any further,
+    /// e.g. 3x^2 + 2x + 1 divided by 2x^2 gives 1 and x^2 + 2x + 1. The identity still holds, but the
+    /// remainder can keep a degree >= deg(p). For a remainder of lower degree over the integers,
+    /// see pseudo_divide, and for a quotient known to be exact, see exact_divide.
     pub fn divide_by(&self, p:&Polynomial<T>) -> Option<(Polynomial<T>, Polynomial<T>)> {
         // (P1, P2) = (Quotient, Remainder)
         if p.is_zero(){
@@ -179,42 +237,94 @@ impl <T> Polynomial<T>
         let dividee_deg = dividee.deg();
         let divider_deg = p.deg();
         if dividee_deg < divider_deg {
-            return Some((Polynomial::zero(), Polynomial{coeffs: p.coeffs.clone()}))
+            return Some((Polynomial::zero(), dividee))
         }
         let mut quotient = vec![T::zero(); dividee_deg - divider_deg + 1];
         let remainder = Polynomial::_long_div(dividee, p, &mut quotient);
-        Some((Polynomial{coeffs: Array1::from_vec(quotient)}, remainder))
+        Some((Polynomial::no_leading_zeros(quotient), remainder))
     }
 
+    /// self / p when p divides self, i.e. the remainder of divide_by is 0.
+    /// Over the integers this is exact whenever p divides self in Z[x].
+    pub fn exact_divide(&self, p:&Polynomial<T>) -> Result<Polynomial<T>, PolynomialError> {
+        match self.divide_by(p) {
+            None => Err(PolynomialError::DivisionByZero),
+            Some((quotient, remainder)) if remainder.is_zero() => Ok(quotient),
+            Some(_) => Err(PolynomialError::NotDivisible)
+        }
+    }
+
+    /// Pseudo-division, for coefficient rings like the integers where long division gets stuck on the
+    /// leading coefficient. Returns (pseudo quotient, pseudo remainder) with
+    /// lc(p)^(deg(self) - deg(p) + 1) * self = quotient * p + remainder and deg(remainder) < deg(p),
+    /// where lc(p) is the leading coefficient of p. Only +, - and * are used, so nothing is truncated.
+    /// If deg(self) < deg(p), the power is 0 and the result is (0, self). None if p is 0.
+    pub fn pseudo_divide(&self, p:&Polynomial<T>) -> Option<(Polynomial<T>, Polynomial<T>)> {
+        if p.is_zero() {
+            return None
+        }
+        let divider_deg = p.deg();
+        if self.deg() < divider_deg {
+            return Some((Polynomial::zero(), self.copy()))
+        }
+        let divider = p.coeffs.as_slice().unwrap();
+        let lead = p.highest_coeff();
+        let mut remainder = self.coeffs.to_vec();
+        let mut quotient = vec![T::zero(); self.len() - divider_deg];
+        // one step per quotient coefficient, also when it is 0, so there are exactly deg(self) - deg(p) + 1
+        // multiplications by lc(p)
+        for k in (0..quotient.len()).rev() {
+            // remainder has k + divider_deg + 1 coefficients here, and lc(p) * c - c * lc(p) = 0
+            let c = remainder.pop().unwrap();
+            remainder.iter_mut().for_each(|r| *r = *r * lead);
+            for (r, d) in remainder[k..].iter_mut().zip(divider.iter()) {
+                *r = *r - c * *d;
+            }
+            quotient.iter_mut().skip(k+1).for_each(|q| *q = *q * lead);
+            quotient[k] = c;
+        }
+        if remainder.is_empty() {
+            remainder.push(T::zero());
+        }
+        Some((Polynomial::no_leading_zeros(quotient), Polynomial::no_leading_zeros(remainder)))
+    }
 
     fn _long_div(
         dividee:Polynomial<T>
         , divider:&Polynomial<T>
-        , quotient:&mut Vec<T>
-    ) -> Polynomial<T> { // returns the remainder    
-        let dividee_deg = dividee.deg();
+        , quotient:&mut [T]
+    ) -> Polynomial<T> { // returns the remainder
         let divider_deg = divider.deg();
-        if dividee_deg < divider_deg {// dividee becomes remainder
-            return  dividee
-        } else {
-            // dividee_deg >= divider_deg.
-            let new_term_deg = dividee_deg - divider_deg; // always >= 0
-            let new_term_coeff = dividee.highest_coeff() / divider.highest_coeff();
-            // modify the quotient
-            if new_term_coeff == T::zero() || quotient[new_term_deg] != T::zero() {
-                // This means that we did not reduce degree. 
+        let divider_coeffs = divider.coeffs.as_slice().unwrap();
+        let lead = divider.highest_coeff();
+        let mut remainder = dividee.coeffs.to_vec();
+        // while deg(remainder) >= deg(divider)
+        while remainder.len() > divider_deg {
+            let new_term_deg = remainder.len() - 1 - divider_deg;
+            let new_term_coeff = *remainder.last().unwrap() / lead;
+            if new_term_coeff == T::zero() {
+                // This means that we can not reduce degree. 
                 // This might happen when we are working with Polynomials over integers.
-                // E.g x^2 + 1 divided by 2x^2 in Z[x], or 3x^2 + 1 divided by 2x^2
-                return dividee // this is the remainder
-            } else { // first time setting coeff for this deg
-                quotient[new_term_deg] = new_term_coeff;
-                // update dividee
-                let basis = Polynomial::basis(new_term_coeff, new_term_deg);
-                let new_dividee = dividee.minus(&basis.multiply(divider));
-                // println!("Division steps: dividing {} by {}", new_dividee, divider);
-                Self::_long_div(new_dividee, divider, quotient)
+                // E.g x^2 + 1 divided by 2x^2 in Z[x], or what is left of 3x^2 + 1 divided by 2x^2
+                break
+            }
+            quotient[new_term_deg] = new_term_coeff;
+            // subtract new_term_coeff * x^new_term_deg * divider, in place
+            for (r, d) in remainder[new_term_deg..].iter_mut().zip(divider_coeffs.iter()) {
+                *r = *r - new_term_coeff * *d;
+            }
+            // With floats, r - (r / lead) * lead can be a rounding error instead of 0, so set the eliminated
+            // term to 0 explicitly. What T's division truncates to 0 is really left over, e.g. x^2 from
+            // 3x^2 - 1 * 2x^2 over the integers, and stops the next step.
+            let leftover = remainder.last_mut().unwrap();
+            if *leftover / lead != T::zero() {
+                *leftover = T::zero();
+            }
+            while remainder.len() > 1 && *remainder.last().unwrap() == T::zero() {
+                remainder.pop();
             }
         }
+        Polynomial::no_leading_zeros(remainder)
     }
 
     // also known as formal derivative
@@ -237,7 +347,7 @@ impl <T> Polynomial<T>
     // This might seem dumb, but this works for general T, even when T is not real or complex numbers.
     fn _fast_self_add(value:T, times:usize) -> T {
         if value == T::zero() {
-            return value;
+            value
         } else {
             match times {
                 0|1 => value,
@@ -253,29 +363,437 @@ impl <T> Polynomial<T>
         }   
     }
 
-    // raise a polynomial p to a deg.
+    /// Schoolbook squaring. The product is symmetric, so each cross term a_i a_j is computed once and doubled,
+    /// which is about half the work of self.multiply(self).
+    pub fn square_schoolbook(&self) -> Polynomial<T> {
+        let a = self.coeffs.as_slice().unwrap();
+        let mut new_poly:Vec<T> = vec![T::zero(); 2*a.len() - 1];
+        for (i, x) in a.iter().enumerate() {
+            new_poly[2*i] = new_poly[2*i] + (*x)*(*x);
+            // 2*a_i, without needing to cast 2 into T
+            let twice = *x + *x;
+            for (j, y) in a.iter().enumerate().skip(i+1) {
+                new_poly[i+j] = new_poly[i+j] + twice*(*y);
+            }
+        }
+        Polynomial::no_leading_zeros(new_poly)
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Choosing a multiplication algorithm for p * q
+
+/// The algorithms p * q can choose from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MulAlgorithm {
+    /// The O(n^2) multiply.
+    Schoolbook,
+    /// karatsuba_mul. Works for every coefficient type, but for fixed-width integers p * q only uses it
+    /// when its intermediate values are sure to fit.
+    Karatsuba,
+    /// toom3_mul. Needs signed coefficients, and for fixed-width integers p * q only uses it when its
+    /// intermediate values are sure to fit.
+    ToomCook3,
+    /// fft_mul without rounding. Only for coefficient types with an FFT.
+    Fft,
+    /// ntt_mul (crt_mul for i128, ring_fft_mul for ModInt), falling back to the best exact algorithm if
+    /// the coefficients are too large for it. Only for i64, i128, u64 and ModInt.
+    Ntt
+}
+
+/// Operand sizes at which p * q switches to a faster algorithm. Each threshold is compared
+/// against the number of coefficients of the shorter operand.
+/// 
+/// The defaults are the crossovers measured by `cargo run -r thresholds`, which times each algorithm
+/// on i64 and f64 operands of 8 to 8192 coefficients and writes threshold_results.csv: Karatsuba
+/// beats schoolbook from 64 coefficients, Toom-3 beats Karatsuba from about 512, ntt_mul beats the
+/// recursive algorithms from 2048 and fft_mul beats schoolbook on f64 from 256. They were measured on
+/// one x86-64 machine and depend on the machine and the coefficient type; rerun it and change them
+/// with set_global if they matter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MulThresholds {
+    /// Below this, Karatsuba is not worth it. Also the size at which karatsuba_mul stops recursing.
+    pub karatsuba: usize,
+    /// Same as karatsuba, for Toom-3. Toom-3 recurses into Karatsuba below this.
+    pub toom3: usize,
+    pub fft: usize,
+    pub ntt: usize
+}
+
+static KARATSUBA_THRESHOLD: AtomicUsize = AtomicUsize::new(MulThresholds::DEFAULT.karatsuba);
+static TOOM3_THRESHOLD: AtomicUsize = AtomicUsize::new(MulThresholds::DEFAULT.toom3);
+static FFT_THRESHOLD: AtomicUsize = AtomicUsize::new(MulThresholds::DEFAULT.fft);
+static NTT_THRESHOLD: AtomicUsize = AtomicUsize::new(MulThresholds::DEFAULT.ntt);
+
+// See threshold_results.csv.
+const KARATSUBA_DEFAULT:usize = 64;
+const TOOM3_DEFAULT:usize = 512;
+const FFT_DEFAULT:usize = 256;
+const NTT_DEFAULT:usize = 2048;
+
+impl MulThresholds {
+    pub const DEFAULT: MulThresholds = MulThresholds{karatsuba: KARATSUBA_DEFAULT, toom3: TOOM3_DEFAULT, fft: FFT_DEFAULT, ntt: NTT_DEFAULT};
+
+    /// The thresholds used by p * q.
+    pub fn global() -> MulThresholds {
+        MulThresholds{
+            karatsuba: KARATSUBA_THRESHOLD.load(Ordering::Relaxed),
+            toom3: TOOM3_THRESHOLD.load(Ordering::Relaxed),
+            fft: FFT_THRESHOLD.load(Ordering::Relaxed),
+            ntt: NTT_THRESHOLD.load(Ordering::Relaxed)
+        }
+    }
+
+    /// Changes the thresholds used by p * q, for every thread.
+    pub fn set_global(thresholds:MulThresholds) {
+        KARATSUBA_THRESHOLD.store(thresholds.karatsuba, Ordering::Relaxed);
+        TOOM3_THRESHOLD.store(thresholds.toom3, Ordering::Relaxed);
+        FFT_THRESHOLD.store(thresholds.fft, Ordering::Relaxed);
+        NTT_THRESHOLD.store(thresholds.ntt, Ordering::Relaxed);
+    }
+}
+
+impl Default for MulThresholds {
+    fn default() -> Self {
+        MulThresholds::DEFAULT
+    }
+}
+
+/// Coefficient types that p * q can multiply. The provided methods use Karatsuba from
+/// MulThresholds::karatsuba on and schoolbook below it, types with faster algorithms override them.
+/// 
+/// To use * on Polynomial<T> for your own T, add `impl MulDispatch for T {}`. If Karatsuba's
+/// intermediate sums can overflow T, override mul_algorithm to return MulAlgorithm::Schoolbook,
+/// which is what p * q did before it had any other algorithm.
+pub trait MulDispatch: Num + Clone + Copy + Display {
+    /// The algorithm p * q uses when the operands have p_len and q_len coefficients.
+    fn mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+        if p_len.min(q_len) >= thresholds.karatsuba {
+            MulAlgorithm::Karatsuba
+        } else {
+            MulAlgorithm::Schoolbook
+        }
+    }
+
+    /// Multiplies p and q with the given algorithm. Algorithms the type does not support fall back to schoolbook.
+    fn mul_using(p:&Polynomial<Self>, q:&Polynomial<Self>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<Self> {
+        match algorithm {
+            MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+            _ => p.multiply(q)
+        }
+    }
+}
+
+// Fixed-width integers. The intermediate values of Karatsuba and Toom-3 can overflow them even when every
+// coefficient of the product fits, so their mul_using checks the coefficients first.
+trait FixedWidth: MulDispatch {
+    // number of bits for the absolute value of a coefficient
+    const VALUE_BITS: u32;
+    fn magnitude(self) -> u128;
+}
+
+macro_rules! impl_fixed_width {
+    (signed: $($s:ty),*; unsigned: $($u:ty),*) => {
+        $(impl FixedWidth for $s {
+            const VALUE_BITS: u32 = <$s>::BITS - 1;
+            fn magnitude(self) -> u128 {self.unsigned_abs() as u128}
+        })*
+        $(impl FixedWidth for $u {
+            const VALUE_BITS: u32 = <$u>::BITS;
+            fn magnitude(self) -> u128 {self as u128}
+        })*
+    };
+}
+
+impl_fixed_width!(signed: i8, i16, i32, i64, i128, isize; unsigned: u8, u16, u32, u64, u128, usize);
+
+// Number of times an operand of len coefficients is split into parts pieces before it gets shorter than cutoff.
+// cutoff must be at least 2.
+fn split_levels(mut len:usize, parts:usize, cutoff:usize) -> u32 {
+    let mut levels = 0;
+    while len >= cutoff {
+        len = len.div_ceil(parts);
+        levels += 1;
+    }
+    levels
+}
+
+// The fastest of algorithm, Karatsuba and schoolbook whose intermediate values can not overflow T.
+// Schoolbook only overflows when the product itself does. Algorithms other than Karatsuba and Toom-3
+// are returned as they are.
+//
+// Every level of Karatsuba adds two halves of each operand, which at most doubles the largest absolute
+// value of a coefficient without changing the sum of the absolute values. The largest product_coeff_bound
+// over the recursion is therefore at most that of p and q times 2^levels. Every level of Toom-3 evaluates
+// at -2, which multiplies the sum of the absolute values by at most 4 and the largest one by at most 7,
+// and the interpolation adds up to 31 times the bound of the level above, so it needs 5 bits per level.
+fn fitting_mul_algorithm<T:FixedWidth>(p:&Polynomial<T>, q:&Polynomial<T>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> MulAlgorithm {
+    if algorithm != MulAlgorithm::Karatsuba && algorithm != MulAlgorithm::ToomCook3 {
+        return algorithm
+    }
+    let abs = |p:&Polynomial<T>| p.coeffs.iter().map(|x| x.magnitude()).collect::<Vec<u128>>();
+    let product_bits = bit_length(product_coeff_bound(&abs(p), &abs(q)));
+    // The longer operand is cut into pieces of the shorter one's length first.
+    let len = p.len().max(q.len()).min(2 * p.len().min(q.len()));
+    let karatsuba_cutoff = thresholds.karatsuba.max(2);
+    if algorithm == MulAlgorithm::ToomCook3 {
+        let toom3_cutoff = thresholds.toom3.max(3);
+        // Toom-3 hands operands with fewer than toom3_cutoff coefficients to Karatsuba
+        let karatsuba_levels = split_levels(len.min(2 * toom3_cutoff), 2, karatsuba_cutoff);
+        if product_bits + 5 * split_levels(len, 3, toom3_cutoff) + karatsuba_levels <= T::VALUE_BITS {
+            return MulAlgorithm::ToomCook3
+        }
+    }
+    if product_bits + split_levels(len, 2, karatsuba_cutoff) <= T::VALUE_BITS {
+        MulAlgorithm::Karatsuba
+    } else {
+        MulAlgorithm::Schoolbook
+    }
+}
+
+macro_rules! impl_mul_dispatch_unsigned {
+    ($($t:ty),*) => {
+        $(impl MulDispatch for $t {
+            fn mul_using(p:&Polynomial<$t>, q:&Polynomial<$t>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<$t> {
+                match fitting_mul_algorithm(p, q, algorithm, thresholds) {
+                    MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+                    _ => p.multiply(q)
+                }
+            }
+        })*
+    };
+}
+
+impl_mul_dispatch_unsigned!(u8, u16, u32, u128, usize);
+
+// Signed integers can also use Toom-3.
+fn signed_mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+    let shorter = p_len.min(q_len);
+    if shorter >= thresholds.toom3 {
+        MulAlgorithm::ToomCook3
+    } else if shorter >= thresholds.karatsuba {
+        MulAlgorithm::Karatsuba
+    } else {
+        MulAlgorithm::Schoolbook
+    }
+}
+
+macro_rules! impl_mul_dispatch_signed {
+    ($($t:ty),*) => {
+        $(impl MulDispatch for $t {
+            fn mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+                signed_mul_algorithm(p_len, q_len, thresholds)
+            }
+
+            fn mul_using(p:&Polynomial<$t>, q:&Polynomial<$t>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<$t> {
+                match fitting_mul_algorithm(p, q, algorithm, thresholds) {
+                    MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+                    MulAlgorithm::ToomCook3 => p.toom3_mul_with(q, thresholds),
+                    _ => p.multiply(q)
+                }
+            }
+        })*
+    };
+}
+
+impl_mul_dispatch_signed!(i8, i16, i32, isize);
+
+impl MulDispatch for i64 {
+    fn mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+        if p_len.min(q_len) >= thresholds.ntt {
+            MulAlgorithm::Ntt
+        } else {
+            signed_mul_algorithm(p_len, q_len, thresholds)
+        }
+    }
+
+    fn mul_using(p:&Polynomial<i64>, q:&Polynomial<i64>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<i64> {
+        match fitting_mul_algorithm(p, q, algorithm, thresholds) {
+            MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+            MulAlgorithm::ToomCook3 => p.toom3_mul_with(q, thresholds),
+            MulAlgorithm::Ntt => p.ntt_mul(q).unwrap_or_else(|_| i64::mul_using(p, q, MulAlgorithm::ToomCook3, thresholds)),
+            _ => p.multiply(q)
+        }
+    }
+}
+
+impl MulDispatch for i128 {
+    fn mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+        if p_len.min(q_len) >= thresholds.ntt {
+            MulAlgorithm::Ntt
+        } else {
+            signed_mul_algorithm(p_len, q_len, thresholds)
+        }
+    }
+
+    fn mul_using(p:&Polynomial<i128>, q:&Polynomial<i128>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<i128> {
+        match fitting_mul_algorithm(p, q, algorithm, thresholds) {
+            MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+            MulAlgorithm::ToomCook3 => p.toom3_mul_with(q, thresholds),
+            MulAlgorithm::Ntt => p.crt_mul(q).unwrap_or_else(|_| i128::mul_using(p, q, MulAlgorithm::ToomCook3, thresholds)),
+            _ => p.multiply(q)
+        }
+    }
+}
+
+impl MulDispatch for u64 {
+    fn mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+        let shorter = p_len.min(q_len);
+        if shorter >= thresholds.ntt {
+            MulAlgorithm::Ntt
+        } else if shorter >= thresholds.karatsuba {
+            MulAlgorithm::Karatsuba
+        } else {
+            MulAlgorithm::Schoolbook
+        }
+    }
+
+    fn mul_using(p:&Polynomial<u64>, q:&Polynomial<u64>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<u64> {
+        match fitting_mul_algorithm(p, q, algorithm, thresholds) {
+            MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+            MulAlgorithm::Ntt => p.ntt_mul(q).unwrap_or_else(|_| u64::mul_using(p, q, MulAlgorithm::Karatsuba, thresholds)),
+            _ => p.multiply(q)
+        }
+    }
+}
+
+// Floating point types, real or complex, have an FFT.
+fn fft_mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+    // Karatsuba is skipped on purpose: the vectorized schoolbook loop beats it
+    // all the way up to where FFT takes over.
+    if p_len.min(q_len) >= thresholds.fft {
+        MulAlgorithm::Fft
+    } else {
+        MulAlgorithm::Schoolbook
+    }
+}
+
+macro_rules! impl_mul_dispatch_float {
+    ($($t:ty),*) => {
+        $(impl MulDispatch for $t {
+            fn mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+                fft_mul_algorithm(p_len, q_len, thresholds)
+            }
+
+            fn mul_using(p:&Polynomial<$t>, q:&Polynomial<$t>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<$t> {
+                match algorithm {
+                    MulAlgorithm::Schoolbook => p.multiply(q),
+                    MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+                    MulAlgorithm::ToomCook3 => p.toom3_mul_with(q, thresholds),
+                    MulAlgorithm::Fft => p.fft_mul_unrounded(q),
+                    MulAlgorithm::Ntt => p.multiply(q)
+                }
+            }
+        })*
+    };
+}
+
+impl_mul_dispatch_float!(f32, f64);
+
+impl <F> MulDispatch for Complex<F> 
+    where F: FftFloat
+{
+    fn mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+        fft_mul_algorithm(p_len, q_len, thresholds)
+    }
+
+    fn mul_using(p:&Polynomial<Complex<F>>, q:&Polynomial<Complex<F>>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<Complex<F>> {
+        match algorithm {
+            MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+            MulAlgorithm::Fft => p.fft_mul(q, RoundingPolicy::None),
+            _ => p.multiply(q)
+        }
+    }
+}
+
+// Complex integers have no FFT, and Karatsuba could overflow them, so they always use schoolbook.
+macro_rules! impl_mul_dispatch_complex_int {
+    ($($t:ty),*) => {
+        $(impl MulDispatch for Complex<$t> {
+            fn mul_algorithm(_p_len:usize, _q_len:usize, _thresholds:&MulThresholds) -> MulAlgorithm {
+                MulAlgorithm::Schoolbook
+            }
+        })*
+    };
+}
+
+impl_mul_dispatch_complex_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl <T> Polynomial<T> 
+    where T: Num + Clone + Copy + Display + Signed
+{
+    /// Toom-Cook 3-way multiplication, O(n^1.465). The interpolation divides by 2 and 3, which is exact
+    /// for integers as long as nothing overflowed, and for big integers and rationals. For fixed-width
+    /// integers the evaluation at -2 and the interpolation need about 5 more bits per level of the
+    /// recursion than the product itself, so toom3_mul can overflow where multiply would not. p * q checks
+    /// for this and falls back to Karatsuba or schoolbook. Floats get rounding errors from the divisions.
+    /// Uses the global MulThresholds to decide when to recurse into Karatsuba.
+    #[inline]
+    pub fn toom3_mul(&self, p:&Polynomial<T>) -> Polynomial<T> {
+        self.toom3_mul_with(p, &MulThresholds::global())
+    }
+
+    /// Toom-3 that hands operands shorter than thresholds.toom3 to Karatsuba,
+    /// which hands those shorter than thresholds.karatsuba to schoolbook.
+    pub fn toom3_mul_with(&self, p:&Polynomial<T>, thresholds:&MulThresholds) -> Polynomial<T> {
+        let a = self.coeffs.as_slice().unwrap();
+        let b = p.coeffs.as_slice().unwrap();
+        // Toom-3 needs at least 3 coefficients in each operand to split.
+        let cutoffs = MulThresholds{karatsuba: thresholds.karatsuba.max(2), toom3: thresholds.toom3.max(3), ..*thresholds};
+        Polynomial::no_leading_zeros(toom3(a, b, &cutoffs))
+    }
+}
+
+impl <T> Polynomial<T> 
+    where T: MulDispatch
+{
+    /// The algorithm p * q would use for self and q under the given thresholds. For fixed-width integers,
+    /// p * q still falls back to a slower exact algorithm when the coefficients are too large for it.
+    #[inline]
+    pub fn mul_algorithm(&self, q:&Polynomial<T>, thresholds:&MulThresholds) -> MulAlgorithm {
+        T::mul_algorithm(self.len(), q.len(), thresholds)
+    }
+
+    /// Same as self * q, but with the given thresholds instead of the global ones.
+    pub fn mul_with(&self, q:&Polynomial<T>, thresholds:&MulThresholds) -> Polynomial<T> {
+        T::mul_using(self, q, self.mul_algorithm(q, thresholds), thresholds)
+    }
+
+    /// self * self with the algorithm p * q would pick. Schoolbook uses the symmetric square_schoolbook,
+    /// and the transform based algorithms only run one forward transform.
+    #[inline]
+    pub fn square(&self) -> Polynomial<T> {
+        self.square_with(&MulThresholds::global())
+    }
+
+    /// Same as square, but with the given thresholds instead of the global ones.
+    pub fn square_with(&self, thresholds:&MulThresholds) -> Polynomial<T> {
+        match self.mul_algorithm(self, thresholds) {
+            MulAlgorithm::Schoolbook => self.square_schoolbook(),
+            algorithm => T::mul_using(self, self, algorithm, thresholds)
+        }
+    }
+
+    // raise a polynomial p to a deg. p^0 is 1, for p = 0 as well.
     pub fn pow(&self, n:usize) -> Polynomial<T> {
         match n {
-            0 => {
-                    println!("DON'T DO THIS.");
-                    Polynomial::one()
-                },
+            0 => Polynomial::one(),
             _ => {
                 let cur = self.copy();
-                Self::_power(cur, n)
+                Self::_power(cur, n, &MulThresholds::global())
             }
         }
     }
 
-    fn _power(current:Polynomial<T>, deg:usize) -> Polynomial<T> {
+    fn _power(current:Polynomial<T>, deg:usize, thresholds:&MulThresholds) -> Polynomial<T> {
         match deg {
             0|1 => current,
             _ => {
-                let squared = current.multiply(&current);
+                let squared = current.square_with(thresholds);
                 if deg % 2 == 1 {
-                    Self::_power(squared, (deg-1) >> 1).multiply(&current)
+                    Self::_power(squared, (deg-1) >> 1, thresholds).mul_with(&current, thresholds)
                 } else {
-                    Self::_power(squared, deg >> 1)
+                    Self::_power(squared, deg >> 1, thresholds)
                 }
             }
         }
@@ -292,11 +810,6 @@ impl <T> PartialEq for Polynomial<T>
     fn eq(&self, other: &Self) -> bool {
         self.coeffs == other.coeffs 
     }
-
-    #[inline]
-    fn ne(&self, other: &Self) -> bool {
-        self.coeffs != other.coeffs
-     }
 }
 
 impl <T> Add for Polynomial<T> 
@@ -332,25 +845,63 @@ impl <T> Sub for &Polynomial<T>
 {
     type Output = Polynomial<T>;
     fn sub(self, p:&Polynomial<T>) -> Polynomial<T> {
-        self.minus(&p)
+        self.minus(p)
     }
 }
 
+// p * q picks the multiplication algorithm with MulDispatch, using the global MulThresholds.
 impl <T> Mul for Polynomial<T> 
-    where T: Num + Clone + Copy + Display
+    where T: MulDispatch
 {
     type Output = Self;
     fn mul(self, p:Polynomial<T>) -> Polynomial<T> {
-        self.multiply(&p)
+        self.mul_with(&p, &MulThresholds::global())
     }
 }
 
 impl <T> Mul for &Polynomial<T> 
-    where T: Num + Clone + Copy + Display
+    where T: MulDispatch
 {
     type Output = Polynomial<T>;
     fn mul(self, p:&Polynomial<T>) -> Polynomial<T> {
-        self.multiply(&p)
+        self.mul_with(p, &MulThresholds::global())
+    }
+}
+
+// p / q and p % q are div_rem, so only over fields. They panic when q is 0, like integer division.
+impl <T> Div for Polynomial<T> 
+    where T: Field
+{
+    type Output = Self;
+    fn div(self, p:Polynomial<T>) -> Polynomial<T> {
+        self.div_rem(&p).expect("Division by the zero polynomial.").0
+    }
+}
+
+impl <T> Div for &Polynomial<T> 
+    where T: Field
+{
+    type Output = Polynomial<T>;
+    fn div(self, p:&Polynomial<T>) -> Polynomial<T> {
+        self.div_rem(p).expect("Division by the zero polynomial.").0
+    }
+}
+
+impl <T> Rem for Polynomial<T> 
+    where T: Field
+{
+    type Output = Self;
+    fn rem(self, p:Polynomial<T>) -> Polynomial<T> {
+        self.div_rem(&p).expect("Division by the zero polynomial.").1
+    }
+}
+
+impl <T> Rem for &Polynomial<T> 
+    where T: Field
+{
+    type Output = Polynomial<T>;
+    fn rem(self, p:&Polynomial<T>) -> Polynomial<T> {
+        self.div_rem(p).expect("Division by the zero polynomial.").1
     }
 }
 
@@ -369,7 +920,7 @@ impl <T> Zero for Polynomial<T>
 }
 
 impl <T> One for Polynomial<T> 
-    where T: Num + Clone + Copy + Display
+    where T: MulDispatch
 {
     #[inline]
     fn one() -> Self {
@@ -396,7 +947,7 @@ impl <T> std::fmt::Display for Polynomial<T>
         let mut p = String::new();
         for (i,coef) in self.coeffs.iter().enumerate().rev() {
             if coef.is_zero() { 
-                if i == 0 && p.len() == 0 {
+                if i == 0 && p.is_empty() {
                     p.push_str(&coef.to_string());
                 }    
                 continue
@@ -415,7 +966,7 @@ impl <T> std::fmt::Display for Polynomial<T>
             } else { // not leading term
                 if i == 0 { // constant term
                     if !coef.is_zero() {
-                        term.push_str(&" + ");
+                        term.push_str(" + ");
                         let coef_str = &coef.to_string();
                         if coef_str.starts_with("-") { // ad-hoc catch of 'negative' values of T
                             term.push('(');
@@ -426,7 +977,7 @@ impl <T> std::fmt::Display for Polynomial<T>
                         }
                     }
                 } else { // non leading, non constant terms
-                    term.push_str(&" + ");
+                    term.push_str(" + ");
                     if !coef.is_one() {
                         let coef_str = &coef.to_string();
                         if coef_str.starts_with("-") { // ad-hoc catch of 'negative' values of T
@@ -453,135 +1004,2826 @@ impl <T> std::fmt::Display for Polynomial<T>
 }
 
 //-------------------------------------------------------------------------------------------------------------
-// Only for real polynomials. Can be made slightly more general. But I will stop here.
-impl Polynomial<f64> {
-
-    #[inline]
-    pub fn get_value_repr(&self) -> Array1<Complex64> {
-        Self::fft(self.coeffs.view())
-    }
-
-    fn fft(p:ArrayView1<f64>) -> Array1<Complex64> {
-        // returns the value representation of p, 
-        let n = p.len();
-        if n == 1 {
-            return Array1::from_elem(1, Complex::new(p[0], 0.))
-        }
-        let deg = 2.0 * PI / (n as f64);
-        let w_n = Complex64::new(deg.cos(), deg.sin());
-        let even = p.slice(s![..;2]);
-        let odd = p.slice(s![1..p.len();2]);
-        let y_e = Self::fft(even);
-        let y_o = Self::fft(odd);
-        let mut y = Array1::from_elem(n, Complex64::zero());
-        let mut w = Complex64::one();
-        let half = n/2;
-        for j in 0..half {
-            let odd_term = w * y_o[j];
-            y[j] = y_e[j] + odd_term;
-            y[j + half] = y_e[j] - odd_term;
-            w *= w_n;
+// FFT for polynomials over the floating point types, f64 and f32, and over their complex numbers.
+
+/// What fft_mul does with the coefficients it computes, which carry a small floating point error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoundingPolicy {
+    /// Keep them as they are.
+    None,
+    /// Round to this many decimal places. Halfway cases round away from zero.
+    RoundToDecimals(usize),
+    /// Round to the nearest integer, for products of polynomials with integer coefficients.
+    RoundToInteger,
+    /// Set coefficients whose absolute value is below eps to 0 and keep the others as they are.
+    SnapNearZero(f64)
+}
+
+impl RoundingPolicy {
+    pub fn apply(&self, x:f64) -> f64 {
+        match self {
+            RoundingPolicy::None => x,
+            RoundingPolicy::RoundToDecimals(decimal_places) => {
+                let rounding_factor = 10f64.powi(*decimal_places as i32);
+                (x * rounding_factor).round() / rounding_factor
+            },
+            RoundingPolicy::RoundToInteger => x.round(),
+            RoundingPolicy::SnapNearZero(eps) => if x.abs() < *eps {0.} else {x}
         }
-        y
     }
 
-
-    fn inverse_fft(p:ArrayView1<Complex64>) -> Array1<Complex64> {
-    
-        let n = p.len();
-        if n == 1 { 
-            return Array1::from_elem(1, p[0])
+    // apply for f32 as well, through f64, which holds every f32 exactly.
+    fn apply_to<F:FftFloat>(&self, x:F) -> F {
+        match self {
+            RoundingPolicy::None => x,
+            _ => F::from_f64(self.apply(x.to_f64().unwrap()))
         }
-    
-        let deg = -2.0 * PI / (n as f64);
-        let w_n = Complex64::new(deg.cos(), deg.sin());
-        let even = p.slice(s![..;2]);
-        let odd = p.slice(s![1..p.len();2]);
-        let y_e = Self::inverse_fft(even);
-        let y_o = Self::inverse_fft(odd);
-        let mut y = Array1::from_elem(n, Complex64::zero());
-        let mut w = Complex64::one();
-        let half = n/2;
-        for j in 0..half {
-            let odd_term = w * y_o[j];
-            y[j] = y_e[j] + odd_term;
-            y[j + half] = y_e[j] - odd_term;
-            w *= w_n;
+    }
+}
+
+impl From<usize> for RoundingPolicy {
+    fn from(decimal_places:usize) -> RoundingPolicy {
+        RoundingPolicy::RoundToDecimals(decimal_places)
+    }
+}
+
+impl <F> Polynomial<F>
+where F: FftFloat {
+
+    #[inline]
+    pub fn get_value_repr(&self) -> Array1<Complex<F>> {
+        self.get_value_repr_at(self.len())
+    }
+
+    /// The values of self at the n-th roots of unity w^0, w^1, ..., w^(n-1), w = e^(2*pi*i/n), for any n > 0.
+    /// If n is less than the number of coefficients, the coefficient of x^k is folded onto x^(k mod n),
+    /// which does not change the values since w^n = 1.
+    pub fn get_value_repr_at(&self, n:usize) -> Array1<Complex<F>> {
+        assert!(n > 0, "Need at least one root of unity.");
+        let mut y = Array1::from_elem(n, Complex::zero());
+        for (k, c) in self.coeffs.iter().enumerate() {
+            y[k % n].re += *c;
         }
+        fft(y.as_slice_mut().unwrap());
         y
     }
 
-    fn smallest_pow_2(n:usize) -> usize {
-        // smallest power of 2 that is >= n
-        let mut test:usize = 1;
-        while test < n {
-            test <<= 1;
-        }
-        test
+    /// The inverse of get_value_repr: the polynomial whose values at the n-th roots of unity are values.
+    /// Only the real parts of the coefficients are kept.
+    pub fn from_value_repr(values:ArrayView1<Complex<F>>) -> Polynomial<F> {
+        let mut coeffs = values.to_vec();
+        inverse_fft(&mut coeffs);
+        Polynomial::no_leading_zeros(coeffs.into_iter().map(|z| z.re).collect())
     }
-    
-    fn with_leading_zeros(&self, target_len:usize) -> Array1<f64>{
-        let q = self.get_coeffs_view();
-        let mut new_array = Array1::from_elem(target_len, 0.);
-        for i in 0..q.len() {
-            new_array[i] = q[i];
-        }
-        new_array
+
+    fn from_rounded(values:Vec<F>, rounding:RoundingPolicy) -> Polynomial<F> {
+        Polynomial::no_leading_zeros(values.into_iter().map(|x| rounding.apply_to(x)).collect())
     }
     
     /// Performs polynomial multiplication using FFT
     /// 
     /// self
-    /// q: &Polynomial<f64>
-    /// decimal_places: how many decimal places should the output keep? FFT has some small numerical error. 
+    /// q: &Polynomial<F>, with F = f64 or f32
+    /// rounding: what to do with the output? FFT has some small numerical error. 
     /// Although it is small, it will often give 0.000000000000012312 instead of 0. 
-    /// You can decide what precision you need for your output.
+    /// You can decide what precision you need for your output, see RoundingPolicy. A plain number of
+    /// decimal places, like 5, means RoundingPolicy::RoundToDecimals(5).
+    /// 
+    /// The FFT plan for the padded length is taken from a thread local cache, so repeated calls at the
+    /// same sizes only pay for the transforms.
     /// 
     /// returns: product of self and q 
-    pub fn fft_mul(&self, q:&Polynomial<f64>, decimal_places:usize) -> Polynomial<f64> {
+    pub fn fft_mul(&self, q:&Polynomial<F>, rounding:impl Into<RoundingPolicy>) -> Polynomial<F> {
         let q_deg = q.deg();
         let p_deg = self.deg();
         if q_deg == 0 || p_deg == 0 {
             return self.multiply(q)
         }
         // q.deg + p.deg + 1 = length of the output
-        let target_len = Self::smallest_pow_2(p_deg + q_deg + 1);
-        // 
-        let new_p = self.with_leading_zeros(target_len);
-        let new_q = q.with_leading_zeros(target_len);
-        // pointwise multiplication, then apply inverse
-        let product_ptwise = Self::fft(new_p.view()) * Self::fft(new_q.view());
-        let new_coeff_array = Self::inverse_fft(product_ptwise.view())/(target_len as f64);
-        // extract real parts and return, round real parts to 5 decimals.
-        let rounding_factor = (10.).powi(decimal_places as i32);
-        Polynomial::no_leading_zeros(new_coeff_array.map(|z| ((z.re()*rounding_factor).trunc())/rounding_factor).to_vec())
+        let target_len = FftPlan::padded_len(p_deg + q_deg + 1);
+        let rounding = rounding.into();
+        FftPlan::with_cached(target_len, |plan| self.fft_mul_with_plan(q, rounding, plan))
+    }
+
+    /// Same as fft_mul, but uses the given plan. plan.len() must be at least self.deg() + q.deg() + 1.
+    pub fn fft_mul_with_plan(&self, q:&Polynomial<F>, rounding:impl Into<RoundingPolicy>, plan:&mut FftPlan<F>) -> Polynomial<F> {
+        let q_deg = q.deg();
+        let p_deg = self.deg();
+        if q_deg == 0 || p_deg == 0 {
+            return self.multiply(q)
+        }
+        Self::from_rounded(self.fft_product(q, plan, 1), rounding.into())
+    }
+
+    /// Same as fft_mul, but the transforms are split across the threads that parallelism allows.
+    /// The result does not depend on the number of threads, it is always identical to fft_mul.
+    pub fn fft_mul_parallel(&self, q:&Polynomial<F>, rounding:impl Into<RoundingPolicy>, parallelism:Parallelism) -> Polynomial<F> {
+        let q_deg = q.deg();
+        let p_deg = self.deg();
+        if q_deg == 0 || p_deg == 0 {
+            return self.multiply(q)
+        }
+        let target_len = FftPlan::padded_len(p_deg + q_deg + 1);
+        let rounding = rounding.into();
+        FftPlan::with_cached(target_len, |plan| self.fft_mul_parallel_with_plan(q, rounding, parallelism, plan))
+    }
+
+    /// Same as fft_mul_parallel, but uses the given plan. plan.len() must be at least self.deg() + q.deg() + 1.
+    pub fn fft_mul_parallel_with_plan(&self, q:&Polynomial<F>, rounding:impl Into<RoundingPolicy>, parallelism:Parallelism, plan:&mut FftPlan<F>) -> Polynomial<F> {
+        let q_deg = q.deg();
+        let p_deg = self.deg();
+        if q_deg == 0 || p_deg == 0 {
+            return self.multiply(q)
+        }
+        Self::from_rounded(self.fft_product(q, plan, parallelism.threads()), rounding.into())
+    }
+
+    // Unrounded FFT product, used by p * q. The output has exactly p_deg + q_deg + 1 coefficients,
+    // so the noise in the leading coefficient cannot make it look like a higher degree polynomial.
+    pub(crate) fn fft_mul_unrounded(&self, q:&Polynomial<F>) -> Polynomial<F> {
+        if q.deg() == 0 || self.deg() == 0 {
+            return self.multiply(q)
+        }
+        let target_len = FftPlan::padded_len(self.deg() + q.deg() + 1);
+        FftPlan::with_cached(target_len, |plan| Polynomial::no_leading_zeros(self.fft_product(q, plan, 1)))
+    }
+
+    // The p_deg + q_deg + 1 coefficients of the product of self and q, before any rounding.
+    // Every transform runs on up to threads threads.
+    fn fft_product(&self, q:&Polynomial<F>, plan:&mut FftPlan<F>, threads:usize) -> Vec<F> {
+        plan.convolve_real(self.coeffs.as_slice().unwrap(), q.coeffs.as_slice().unwrap(), threads)
     }
 
-    pub fn fft_mul_threaded(&self, q:&Polynomial<f64>, decimal_places:usize) -> Polynomial<f64> {
+    pub fn fft_mul_threaded(&self, q:&Polynomial<F>, rounding:impl Into<RoundingPolicy>) -> Polynomial<F> {
         let q_deg = q.deg();
         let p_deg = self.deg();
         if q_deg == 0 || p_deg == 0 {
             return self.multiply(q)
         }
         // q.deg + p.deg + 1 = length of the output
-        let target_len = Self::smallest_pow_2(q_deg + p_deg + 1);
-        // 
-        let (fft_p, fft_q) = thread::scope(|s| {
-            let first = s.spawn(|| 
-                Self::fft(self.with_leading_zeros(target_len).view())
-            );
-            let second = s.spawn(|| 
-                Self::fft(q.with_leading_zeros(target_len).view())
-            );
-            (first.join().unwrap(), second.join().unwrap())
-        });
-        // pointwise multiplication, then apply inverse
-        let product_ptwise = fft_p * fft_q;
-        let new_coeff_array = Self::inverse_fft(product_ptwise.view())/(target_len as f64);
-        // extract real parts and return, round real parts to 5 decimals.
-        let rounding_factor = (10.).powi(decimal_places as i32);
-        Polynomial::no_leading_zeros(new_coeff_array.map(|z| ((z.re()*rounding_factor).trunc())/rounding_factor).to_vec())
+        let target_len = FftPlan::padded_len(q_deg + p_deg + 1);
+        let rounding = rounding.into();
+        FftPlan::with_cached(target_len, |plan| self.fft_mul_threaded_with_plan(q, rounding, plan))
+    }
+
+    /// Same as fft_mul_threaded, but uses the given plan. plan.len() must be at least self.deg() + q.deg() + 1.
+    pub fn fft_mul_threaded_with_plan(&self, q:&Polynomial<F>, rounding:impl Into<RoundingPolicy>, plan:&mut FftPlan<F>) -> Polynomial<F> {
+        let q_deg = q.deg();
+        let p_deg = self.deg();
+        if q_deg == 0 || p_deg == 0 {
+            return self.multiply(q)
+        }
+        let out_len = p_deg + q_deg + 1;
+        assert!(plan.len() >= out_len, "FFT plan of length {} is too short for a product of length {}.", plan.len(), out_len);
+        let FftPlan{tables, real, scratch} = plan;
+        let [p_buf, q_buf] = scratch;
+        let tables = &*tables;
+        // Same steps as fft_product, with the two forward transforms on their own threads, so the
+        // results are identical to fft_mul.
+        let product = match real {
+            Some(real) => {
+                let real = &*real;
+                thread::scope(|s| {
+                    s.spawn(|| real.forward(self.coeffs.as_slice().unwrap(), p_buf, 1));
+                    s.spawn(|| real.forward(q.coeffs.as_slice().unwrap(), q_buf, 1));
+                });
+                real.multiply_inverse(p_buf, q_buf, out_len, 1)
+            },
+            None => {
+                thread::scope(|s| {
+                    s.spawn(|| {
+                        load_real(self.coeffs.as_slice().unwrap(), p_buf);
+                        tables.transform(p_buf, false, 1);
+                    });
+                    s.spawn(|| {
+                        load_real(q.coeffs.as_slice().unwrap(), q_buf);
+                        tables.transform(q_buf, false, 1);
+                    });
+                });
+                tables.multiply_inverse(p_buf, q_buf, 1);
+                p_buf[..out_len].iter().map(|z| z.re).collect()
+            }
+        };
+        Self::from_rounded(product, rounding.into())
+    }
+}
+
+impl <F> Polynomial<F>
+where F: FftFloat {
+    /// fft_mul, together with an upper bound on how far any coefficient of the unrounded product can be
+    /// from the exact one. See fft_mul_error_bound.
+    pub fn fft_mul_with_error(&self, q:&Polynomial<F>, rounding:impl Into<RoundingPolicy>) -> (Polynomial<F>, F) {
+        (self.fft_mul(q, rounding), self.fft_mul_error_bound(q))
+    }
+
+    /// An upper bound on the error of every coefficient that fft_mul computes, before it rounds them.
+    /// It only depends on the 2-norms of self and q, on the transform length and on the precision of F,
+    /// so it can be checked before multiplying. If both polynomials have integer coefficients and the bound
+    /// is below 0.5, RoundingPolicy::RoundToInteger is guaranteed to give the exact product.
+    /// 
+    /// The bound holds in the worst case. The actual error is usually a lot smaller, roughly the bound
+    /// divided by the square root of the transform length.
+    pub fn fft_mul_error_bound(&self, q:&Polynomial<F>) -> F {
+        // in f64, so that the norms of f32 polynomials do not overflow
+        let norm = |p:&Polynomial<F>| p.coeffs.iter().map(|x| x.to_f64().unwrap().powi(2)).sum::<f64>().sqrt();
+        let norms = norm(self) * norm(q);
+        if q.deg() == 0 || self.deg() == 0 {
+            // multiply rounds once per coefficient
+            return F::from_f64(norms * gamma::<F>(1))
+        }
+        F::from_f64(norms * convolution_error::<F>(FftPlan::padded_len(self.deg() + q.deg() + 1), 1))
     }
+}
 
+impl <F> Polynomial<Complex<F>>
+where F: FftFloat {
 
+    #[inline]
+    pub fn get_value_repr(&self) -> Array1<Complex<F>> {
+        self.get_value_repr_at(self.len())
+    }
+
+    /// Same as the real get_value_repr_at: the values of self at the n-th roots of unity, for any n > 0.
+    pub fn get_value_repr_at(&self, n:usize) -> Array1<Complex<F>> {
+        assert!(n > 0, "Need at least one root of unity.");
+        let mut y = Array1::from_elem(n, Complex::zero());
+        for (k, c) in self.coeffs.iter().enumerate() {
+            y[k % n] += *c;
+        }
+        fft(y.as_slice_mut().unwrap());
+        y
+    }
+
+    /// The inverse of get_value_repr: the polynomial whose values at the n-th roots of unity are values.
+    pub fn from_value_repr(values:ArrayView1<Complex<F>>) -> Polynomial<Complex<F>> {
+        let mut coeffs = values.to_vec();
+        inverse_fft(&mut coeffs);
+        Polynomial::no_leading_zeros(coeffs)
+    }
+
+    /// Performs polynomial multiplication using FFT. Same as fft_mul for real polynomials, with the
+    /// rounding applied to the real and imaginary parts separately.
+    pub fn fft_mul(&self, q:&Polynomial<Complex<F>>, rounding:impl Into<RoundingPolicy>) -> Polynomial<Complex<F>> {
+        self.fft_mul_parallel(q, rounding, Parallelism::Serial)
+    }
+
+    /// Same as fft_mul, but uses the given plan. plan.len() must be at least self.deg() + q.deg() + 1.
+    pub fn fft_mul_with_plan(&self, q:&Polynomial<Complex<F>>, rounding:impl Into<RoundingPolicy>, plan:&mut FftPlan<F>) -> Polynomial<Complex<F>> {
+        self.fft_mul_parallel_with_plan(q, rounding, Parallelism::Serial, plan)
+    }
+
+    /// Same as fft_mul, but the transforms are split across the threads that parallelism allows.
+    pub fn fft_mul_parallel(&self, q:&Polynomial<Complex<F>>, rounding:impl Into<RoundingPolicy>, parallelism:Parallelism) -> Polynomial<Complex<F>> {
+        let q_deg = q.deg();
+        let p_deg = self.deg();
+        if q_deg == 0 || p_deg == 0 {
+            return self.multiply(q)
+        }
+        let target_len = FftPlan::padded_len(p_deg + q_deg + 1);
+        let rounding = rounding.into();
+        FftPlan::with_cached(target_len, |plan| self.fft_mul_parallel_with_plan(q, rounding, parallelism, plan))
+    }
+
+    /// Same as fft_mul_parallel, but uses the given plan. plan.len() must be at least self.deg() + q.deg() + 1.
+    pub fn fft_mul_parallel_with_plan(&self, q:&Polynomial<Complex<F>>, rounding:impl Into<RoundingPolicy>, parallelism:Parallelism, plan:&mut FftPlan<F>) -> Polynomial<Complex<F>> {
+        let q_deg = q.deg();
+        let p_deg = self.deg();
+        if q_deg == 0 || p_deg == 0 {
+            return self.multiply(q)
+        }
+        let out_len = p_deg + q_deg + 1;
+        assert!(plan.len() >= out_len, "FFT plan of length {} is too short for a product of length {}.", plan.len(), out_len);
+        let rounding = rounding.into();
+        let threads = parallelism.threads();
+        // complex coefficients have no symmetry to exploit, so these are full length transforms
+        let FftPlan{tables, scratch, ..} = plan;
+        let [p_buf, q_buf] = scratch;
+        for (buf, p) in [(&mut *p_buf, self), (&mut *q_buf, q)] {
+            let coeffs = p.get_coeffs_view();
+            for (i, z) in buf.iter_mut().enumerate() {
+                *z = if i < coeffs.len() {coeffs[i]} else {Complex::zero()};
+            }
+            tables.transform(buf, false, threads);
+        }
+        tables.multiply_inverse(p_buf, q_buf, threads);
+        Polynomial::no_leading_zeros(p_buf[..out_len].iter()
+            .map(|z| Complex::new(rounding.apply_to(z.re), rounding.apply_to(z.im))).collect())
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Iterative, in place mixed radix FFT used by the methods above, in f64 or f32.
+// Lengths may have the prime factors 2, 3 and 5. Allowing 3 and 5 next to 2 means fft_mul can pad
+// to a length just above p_deg + q_deg + 1, instead of up to twice that for the next power of 2.
+// Any other length goes through Bluestein's algorithm, which turns a length n DFT into a cyclic
+// convolution of a 2, 3, 5 length of at least 2n - 1. fft_mul never needs it, but fft and
+// get_value_repr_at accept lengths like 7 or 1009.
+
+/// The floating point types the FFT works in. Twiddle factors are always computed in f64 and then
+/// converted, so f32 transforms are as accurate as f32 arithmetic allows.
+pub trait FftFloat: Float + NumAssign + Display + std::fmt::Debug + Send + Sync + 'static {
+    fn from_f64(x:f64) -> Self;
+    /// Runs f with this thread's plans for the type.
+    fn with_plan_cache<R>(f:impl FnOnce(&mut PlanCache<Self>) -> R) -> R;
+}
+
+/// The most plans a PlanCache keeps. Plans for long transforms hold several buffers of their length,
+/// so without a limit a thread that transforms many different lengths would keep all of them alive.
+pub const PLAN_CACHE_CAPACITY:usize = 16;
+
+/// One thread's FFT plans of one float type, keyed by length, as used by FftPlan::with_cached.
+/// Holds at most PLAN_CACHE_CAPACITY plans and drops the least recently used one to make room.
+#[derive(Debug)]
+pub struct PlanCache<F> {
+    // each plan with the clock value of its last use
+    plans: HashMap<usize, (u64, FftPlan<F>)>,
+    clock: u64
+}
+
+impl <F> PlanCache<F> {
+    fn new() -> PlanCache<F> {
+        PlanCache{plans: HashMap::new(), clock: 0}
+    }
+
+    /// Number of cached plans.
+    pub fn len(&self) -> usize {
+        self.plans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plans.is_empty()
+    }
+
+    /// Whether a plan of length len is cached.
+    pub fn contains(&self, len:usize) -> bool {
+        self.plans.contains_key(&len)
+    }
+
+    fn take(&mut self, len:usize) -> Option<FftPlan<F>> {
+        self.plans.remove(&len).map(|(_, plan)| plan)
+    }
+
+    fn put(&mut self, len:usize, plan:FftPlan<F>) {
+        if !self.plans.contains_key(&len) && self.plans.len() >= PLAN_CACHE_CAPACITY {
+            let oldest = self.plans.iter().min_by_key(|(_, (used, _))| *used).map(|(&l, _)| l);
+            if let Some(oldest) = oldest {
+                self.plans.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.plans.insert(len, (self.clock, plan));
+    }
+}
+
+macro_rules! impl_fft_float {
+    ($($t:ty => $cache:ident),*) => {
+        $(
+            thread_local! {
+                static $cache: RefCell<PlanCache<$t>> = RefCell::new(PlanCache::new());
+            }
+
+            impl FftFloat for $t {
+                #[inline]
+                fn from_f64(x:f64) -> $t {
+                    x as $t
+                }
+
+                fn with_plan_cache<R>(f:impl FnOnce(&mut PlanCache<$t>) -> R) -> R {
+                    $cache.with(|plans| f(&mut plans.borrow_mut()))
+                }
+            }
+        )*
+    };
+}
+
+impl_fft_float!(f64 => FFT_PLANS_F64, f32 => FFT_PLANS_F32);
+
+/// How many threads an FFT may use. Transforms shorter than 2^14 points always run on the
+/// calling thread, longer ones start their threads twice, however many stages they have.
+/// The threads only share out the butterflies, so the output is the same for any setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Parallelism {
+    /// Everything on the calling thread.
+    #[default]
+    Serial,
+    /// At most this many threads.
+    Threads(usize),
+    /// One thread for each core that std::thread::available_parallelism reports.
+    Available
+}
+
+impl Parallelism {
+    pub fn threads(&self) -> usize {
+        match self {
+            Parallelism::Serial => 1,
+            Parallelism::Threads(n) => (*n).max(1),
+            Parallelism::Available => thread::available_parallelism().map_or(1, |n| n.get())
+        }
+    }
+}
+
+/// Everything a length n FFT needs that only depends on n: the twiddle factors, the input permutation,
+/// the radix of every stage and two scratch buffers for the operands of a multiplication.
+/// Build one with FftPlan::new and reuse it, or let FftPlan::with_cached keep one per thread.
+/// F is the floating point type the transforms work in, f64 unless stated otherwise.
+#[derive(Debug, Clone)]
+pub struct FftPlan<F = f64> {
+    tables: FftTables<F>,
+    // for even n, what fft_mul needs to transform real sequences with length n/2 transforms
+    real: Option<RealTables<F>>,
+    scratch: [Vec<Complex<F>>; 2]
+}
+
+// The read-only part of a plan. Kept apart from the scratch buffers so that threads can share it
+// while each of them writes to its own buffer.
+#[derive(Debug, Clone)]
+enum FftTables<F> {
+    MixedRadix(MixedRadix<F>),
+    Bluestein(Bluestein<F>)
+}
+
+#[derive(Debug, Clone)]
+struct MixedRadix<F> {
+    len: usize,
+    twiddles: Vec<Complex<F>>,
+    // radix of each butterfly stage, in the order they run
+    radices: Vec<usize>,
+    // the digit reversal permutation, as the swaps that carry it out in place
+    swaps: Vec<(usize, usize)>
+}
+
+#[derive(Debug, Clone)]
+struct Bluestein<F> {
+    len: usize,
+    // chirp[k] = e^(pi*i*k^2/n), so that w^(jk) = chirp[j] * chirp[k] * conj(chirp[k - j])
+    chirp: Vec<Complex<F>>,
+    // forward transform of the conjugated chirp, laid out for a cyclic convolution of length inner.len
+    kernel: Vec<Complex<F>>,
+    inner: MixedRadix<F>
+}
+
+impl FftPlan {
+    /// The length fft_mul pads a product of length n to: the cheapest even length >= n whose only
+    /// prime factors are 2, 3 and 5. Even lengths let fft_mul use transforms of half the length.
+    pub fn padded_len(n:usize) -> usize {
+        // Roughly what one point costs in a stage of each radix, relative to radix 2.
+        let cost = |m:usize| -> f64 {
+            fft_radices(m).unwrap().iter().map(|r| match r {2 => 1., 3 => 2.5, _ => 2.8}).sum::<f64>() * (m as f64)
+        };
+        let upper = n.max(2).next_power_of_two();
+        // Small transforms are dominated by overhead, and radix 2 has the least of it.
+        if upper <= 64 {
+            return upper
+        }
+        let mut best = upper;
+        let mut p5 = 1;
+        while p5 <= upper {
+            let mut p35 = p5;
+            while p35 <= upper {
+                let mut m = 2 * p35;
+                while m < n {
+                    m <<= 1;
+                }
+                if cost(m) < cost(best) {
+                    best = m;
+                }
+                p35 *= 3;
+            }
+            p5 *= 5;
+        }
+        best
+    }
+}
+
+impl <F> FftPlan<F>
+where F: FftFloat {
+    /// Plan for transforms of length len. Lengths with only the prime factors 2, 3 and 5 are the fastest,
+    /// any other positive length costs about as much as three transforms of a 2, 3, 5 length of at least 2 * len.
+    pub fn new(len:usize) -> FftPlan<F> {
+        assert!(len > 0, "FFT length must be positive.");
+        let tables = match MixedRadix::new(len) {
+            Some(mixed) => FftTables::MixedRadix(mixed),
+            None => FftTables::Bluestein(Bluestein::new(len))
+        };
+        FftPlan{
+            tables,
+            real: if len.is_multiple_of(2) {Some(RealTables::new(len))} else {None},
+            scratch: [vec![Complex::zero(); len], vec![Complex::zero(); len]]
+        }
+    }
+
+    /// Runs f with this thread's cached plan of length len, building the plan if it is not cached.
+    /// The cache keeps the PLAN_CACHE_CAPACITY most recently used lengths.
+    pub fn with_cached<R>(len:usize, f:impl FnOnce(&mut FftPlan<F>) -> R) -> R {
+        // The plan is taken out of the cache while f runs, so f may itself use the cache.
+        let mut plan = F::with_plan_cache(|plans| plans.take(len))
+            .unwrap_or_else(|| FftPlan::new(len));
+        let result = f(&mut plan);
+        F::with_plan_cache(|plans| plans.put(len, plan));
+        result
+    }
+
+    // plans always have a positive length, so there is no is_empty
+    #[allow(clippy::len_without_is_empty)]
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Evaluates the polynomial with coefficients buf at the powers of w = e^(2*pi*i/n), in place.
+    pub fn forward(&self, buf:&mut [Complex<F>]) {
+        self.tables.transform(buf, false, 1);
+    }
+
+    /// Inverse of forward, in place. The result is already divided by n.
+    pub fn inverse(&self, buf:&mut [Complex<F>]) {
+        self.tables.transform(buf, true, 1);
+    }
+
+    /// forward, on the threads that parallelism allows.
+    pub fn forward_parallel(&self, buf:&mut [Complex<F>], parallelism:Parallelism) {
+        self.tables.transform(buf, false, parallelism.threads());
+    }
+
+    /// inverse, on the threads that parallelism allows.
+    pub fn inverse_parallel(&self, buf:&mut [Complex<F>], parallelism:Parallelism) {
+        self.tables.transform(buf, true, parallelism.threads());
+    }
+
+    // The a.len() + b.len() - 1 terms of the linear convolution of the real sequences a and b, before any
+    // rounding. Every transform runs on up to threads threads.
+    fn convolve_real(&mut self, a:&[F], b:&[F], threads:usize) -> Vec<F> {
+        let out_len = a.len() + b.len() - 1;
+        assert!(self.len() >= out_len, "FFT plan of length {} is too short for a product of length {}.", self.len(), out_len);
+        let FftPlan{tables, real, scratch} = self;
+        let [p_buf, q_buf] = scratch;
+        // squaring passes the same slice twice, which only needs one forward transform
+        let square = std::ptr::eq(a, b);
+        match real {
+            Some(real) => {
+                real.forward(a, p_buf, threads);
+                if square {
+                    q_buf.copy_from_slice(p_buf);
+                } else {
+                    real.forward(b, q_buf, threads);
+                }
+                real.multiply_inverse(p_buf, q_buf, out_len, threads)
+            },
+            None => {
+                load_real(a, p_buf);
+                tables.transform(p_buf, false, threads);
+                if square {
+                    q_buf.copy_from_slice(p_buf);
+                } else {
+                    load_real(b, q_buf);
+                    tables.transform(q_buf, false, threads);
+                }
+                tables.multiply_inverse(p_buf, q_buf, threads);
+                p_buf[..out_len].iter().map(|z| z.re).collect()
+            }
+        }
+    }
+}
+
+// copies values into the real parts of buf and zero-fills the rest
+fn load_real<F:FftFloat>(values:&[F], buf:&mut [Complex<F>]) {
+    for (i, z) in buf.iter_mut().enumerate() {
+        *z = if i < values.len() {Complex::new(values[i], F::zero())} else {Complex::zero()};
+    }
+}
+
+/// In place FFT of any non-empty buf, using this thread's cached plan for buf.len().
+/// Same convention as FftPlan::forward.
+pub fn fft<F:FftFloat>(buf:&mut [Complex<F>]) {
+    FftPlan::with_cached(buf.len(), |plan| plan.forward(buf));
+}
+
+/// Inverse of fft, already divided by buf.len().
+pub fn inverse_fft<F:FftFloat>(buf:&mut [Complex<F>]) {
+    FftPlan::with_cached(buf.len(), |plan| plan.inverse(buf));
+}
+
+impl <F> FftTables<F>
+where F: FftFloat {
+    fn len(&self) -> usize {
+        match self {
+            FftTables::MixedRadix(mixed) => mixed.len,
+            FftTables::Bluestein(bluestein) => bluestein.len
+        }
+    }
+
+    /// FFT on buf, in place. The forward transform uses w, the inverse uses its conjugate and divides by n.
+    fn transform(&self, buf:&mut [Complex<F>], inverse:bool, threads:usize) {
+        assert_eq!(buf.len(), self.len(), "Buffer length does not match the FFT plan.");
+        match self {
+            FftTables::MixedRadix(mixed) => mixed.transform(buf, inverse, threads),
+            FftTables::Bluestein(bluestein) => bluestein.transform(buf, inverse, threads)
+        }
+    }
+
+    // The product of the full transforms p_buf and q_buf, transformed back into p_buf. For plans
+    // without RealTables, and for complex coefficients.
+    fn multiply_inverse(&self, p_buf:&mut [Complex<F>], q_buf:&[Complex<F>], threads:usize) {
+        // pointwise multiplication, then apply inverse
+        for (a, b) in p_buf.iter_mut().zip(q_buf.iter()) {
+            *a *= b;
+        }
+        self.transform(p_buf, true, threads);
+    }
+}
+
+impl <F> MixedRadix<F>
+where F: FftFloat {
+    fn new(len:usize) -> Option<MixedRadix<F>> {
+        let radices = fft_radices(len)?;
+        Some(MixedRadix{
+            len,
+            twiddles: twiddle_table(len),
+            swaps: digit_reversal_swaps(len, &radices),
+            radices
+        })
+    }
+
+    /// Cooley-Tukey FFT on buf, in place, on up to threads threads.
+    fn transform(&self, buf:&mut [Complex<F>], inverse:bool, threads:usize) {
+        let n = buf.len();
+        // After the digit reversal, the butterflies can run from the smallest block size up without any extra memory.
+        for (i, j) in self.swaps.iter() {
+            buf.swap(*i, *j);
+        }
+        let threads = if n < PARALLEL_FFT_MIN_LEN {1} else {threads.max(1)};
+        // The first stages have at least one block per thread. Their blocks nest, so every thread can run
+        // all of them on its own part of buf.
+        let mut sub = 1;
+        let mut stage = 0;
+        while stage < self.radices.len() && n / (sub * self.radices[stage]) >= threads {
+            sub *= self.radices[stage];
+            stage += 1;
+        }
+        let (early, late) = self.radices.split_at(stage);
+        if threads == 1 {
+            self.run_stages(buf, early, inverse);
+        } else {
+            let part = sub * (n / sub).div_ceil(threads);
+            thread::scope(|s| {
+                for chunk in buf.chunks_mut(part) {
+                    s.spawn(|| self.run_stages(chunk, early, inverse));
+                }
+            });
+        }
+        // The other stages have fewer blocks than threads, so the butterflies within each block are split up instead.
+        // Their butterflies combine entries a multiple of sub apart, so with buf cut into rows of length sub,
+        // every column only ever meets itself. Each thread takes a range of columns through all of these stages.
+        if !late.is_empty() {
+            let width = sub.div_ceil(threads);
+            let mut stripes:Vec<Vec<&mut [Complex<F>]>> = (0..sub.div_ceil(width)).map(|_| Vec::new()).collect();
+            for row in buf.chunks_exact_mut(sub) {
+                for (stripe, piece) in stripes.iter_mut().zip(row.chunks_mut(width)) {
+                    stripe.push(piece);
+                }
+            }
+            thread::scope(|s| {
+                for (c, mut stripe) in stripes.into_iter().enumerate() {
+                    s.spawn(move || self.run_column_stages(&mut stripe, c * width, sub, late, inverse));
+                }
+            });
+        }
+        if inverse {
+            let scale = F::from_f64(1. / (n as f64));
+            buf.iter_mut().for_each(|z| *z *= scale);
+        }
+    }
+
+    // Runs the stages with the given radices on every block of buf, which holds whole blocks of the last of them.
+    fn run_stages(&self, buf:&mut [Complex<F>], radices:&[usize], inverse:bool) {
+        // sub is the length of the transforms that the current stage combines
+        let mut sub = 1;
+        for r in radices {
+            for block in buf.chunks_exact_mut(sub * r) {
+                match r {
+                    2 => self.butterflies(split_rows::<_, 2>(block, sub), 0, sub * 2, inverse),
+                    3 => self.butterflies(split_rows::<_, 3>(block, sub), 0, sub * 3, inverse),
+                    _ => self.butterflies(split_rows::<_, 5>(block, sub), 0, sub * 5, inverse)
+                }
+            }
+            sub *= r;
+        }
+    }
+
+    // Runs the stages with the given radices on the columns j0, j0 + 1, ... of buf, cut into rows of length row_len.
+    // rows holds the part of every row in those columns.
+    fn run_column_stages(&self, rows:&mut [&mut [Complex<F>]], j0:usize, row_len:usize, radices:&[usize], inverse:bool) {
+        // the transforms that the current stage combines span this many rows
+        let mut span = 1;
+        for r in radices {
+            for block in rows.chunks_exact_mut(span * r) {
+                match r {
+                    2 => self.column_butterflies::<2>(block, span, j0, row_len, inverse),
+                    3 => self.column_butterflies::<3>(block, span, j0, row_len, inverse),
+                    _ => self.column_butterflies::<5>(block, span, j0, row_len, inverse)
+                }
+            }
+            span *= r;
+        }
+    }
+
+    // The butterflies of one block of span * R rows. Row k of the u-th transform the block combines is block[k + u * span].
+    fn column_butterflies<const R:usize>(&self, block:&mut [&mut [Complex<F>]], span:usize, j0:usize, row_len:usize, inverse:bool) {
+        for k in 0..span {
+            let mut picked = block.iter_mut().skip(k).step_by(span);
+            let rows:[&mut [Complex<F>]; R] = std::array::from_fn(|_| &mut **picked.next().unwrap());
+            self.butterflies(rows, j0 + k * row_len, row_len * span * R, inverse);
+        }
+    }
+
+    // Radix R butterflies for columns j0, j0 + 1, ... of a block of length size. rows[u][t] is the
+    // entry j0 + t of the u-th transform of length size / R that the block combines.
+    #[inline]
+    fn butterflies<const R:usize>(&self, mut rows:[&mut [Complex<F>]; R], j0:usize, size:usize, inverse:bool) {
+        let stride = self.len / size;
+        for t in 0..rows[0].len() {
+            let j = j0 + t;
+            let mut x = [Complex::zero(); R];
+            for (u, (v, row)) in x.iter_mut().zip(rows.iter()).enumerate() {
+                *v = if u == 0 {row[t]} else {self.twiddle(u * j * stride, inverse) * row[t]};
+            }
+            for (v, row) in small_dft(x, inverse).into_iter().zip(rows.iter_mut()) {
+                row[t] = v;
+            }
+        }
+    }
+
+    #[inline]
+    fn twiddle(&self, k:usize, inverse:bool) -> Complex<F> {
+        if inverse {self.twiddles[k].conj()} else {self.twiddles[k]}
+    }
+}
+
+// The error bounds below are for transforms computed in F, and are themselves computed in f64.
+
+// The unit roundoff of F.
+fn unit_roundoff<F:FftFloat>() -> f64 {
+    F::epsilon().to_f64().unwrap() / 2.
+}
+
+// gamma(k) = k u / (1 - k u), which bounds the relative error of k roundings in F.
+fn gamma<F:FftFloat>(k:usize) -> f64 {
+    let ku = (k as f64) * unit_roundoff::<F>();
+    ku / (1. - ku)
+}
+
+// How far the entries of twiddle_table can be from the true roots of unity, relative to 1. They are 4 f64
+// roundings off, plus one more rounding when F is narrower than f64.
+fn twiddle_error<F:FftFloat>() -> f64 {
+    4. * unit_roundoff::<F>()
+}
+
+// A bound e on the error of a length n transform, as fft_mul runs it. The output is off by at most e times
+// its 2-norm, and every output entry is off by at most e times the sum of the absolute values of the inputs.
+// Each radix r stage multiplies by twiddles, which costs twiddle_error + sqrt(2) gamma_2, and then adds up
+// r terms with unit weights in at most r + 3 roundings of the real and imaginary parts. That is an error of
+// eta times the sum of the r inputs for every output, or sqrt(r) eta relative to the 2-norm of the outputs.
+// The errors of the stages compound. The real transforms of fft_mul have the same stages, with the last
+// radix 2 stage being the untangling step.
+fn transform_error<F:FftFloat>(n:usize) -> f64 {
+    let radices = fft_radices(n).expect("fft_mul only uses lengths with the prime factors 2, 3 and 5.");
+    let w = twiddle_error::<F>();
+    let eta = |r:usize| w + SQRT_2 * gamma::<F>(2) * (1. + w) + SQRT_2 * gamma::<F>(r + 3);
+    radices.iter().map(|r| 1. + (*r as f64).sqrt() * eta(*r)).product::<f64>() - 1.
+}
+
+// The largest error of a coefficient of sum over i of a_i * b_i, computed with transforms of length n that
+// are multiplied pointwise, summed up and transformed back, divided by the sum of |a_i|_2 |b_i|_2.
+// Let A and B be the transforms of a and b, and e the error of one transform. The computed A is off
+// by at most e |A|_2 = e sqrt(n) |a|_2 in the 2-norm, so by Cauchy-Schwarz the computed pointwise
+// product P is off from the exact AB by at most n |a|_2 |b|_2 (2e + e^2 + sqrt(2) gamma_2 (1 + e)^2)
+// in the 1-norm. Summing up the terms adds gamma_(terms-1) times the sum of the |P|_1. The inverse transform
+// divides by n, so that becomes the error of every coefficient, and adds an error of at most
+// (e + gamma_2) |S|_1 / n of its own for the sum S, with |P|_1 <= (1 + e)^2 (1 + sqrt(2) gamma_2) n |a|_2 |b|_2.
+fn convolution_error<F:FftFloat>(n:usize, terms:usize) -> f64 {
+    let e = transform_error::<F>(n);
+    let mul_error = SQRT_2 * gamma::<F>(2);
+    let product_norm = (1. + e) * (1. + e) * (1. + mul_error);
+    let product_error = 2. * e + e * e + mul_error * (1. + e) * (1. + e);
+    let sum_error = gamma::<F>(terms - 1) * product_norm;
+    let inverse_error = (e + gamma::<F>(2)) * (1. + gamma::<F>(terms - 1)) * product_norm;
+    // rounded up a little, for the rounding errors of this computation
+    (product_error + sum_error + inverse_error) * (1. + 1e-6)
+}
+
+// Below this length, starting threads costs more than the transform.
+const PARALLEL_FFT_MIN_LEN:usize = 1 << 14;
+
+// The R consecutive pieces of length sub that block is made of.
+#[inline]
+fn split_rows<F, const R:usize>(block:&mut [Complex<F>], sub:usize) -> [&mut [Complex<F>]; R] {
+    let mut rows = block.chunks_exact_mut(sub);
+    std::array::from_fn(|_| rows.next().unwrap())
+}
+
+// R point DFT for the radices MixedRadix supports.
+#[inline]
+fn small_dft<F:FftFloat, const R:usize>(x:[Complex<F>; R], inverse:bool) -> [Complex<F>; R] {
+    let mut y = x;
+    let x:&[Complex<F>] = &x;
+    match R {
+        2 => {
+            y[0] = x[0] + x[1];
+            y[1] = x[0] - x[1];
+        },
+        3 => y.copy_from_slice(&radix_3([x[0], x[1], x[2]], inverse)),
+        _ => y.copy_from_slice(&radix_5([x[0], x[1], x[2], x[3], x[4]], inverse))
+    }
+    y
+}
+
+// The spectrum X of a real sequence x of length n = 2h is determined by the length h transform Z of
+// z_j = x_2j + i x_2j+1. With E and O the transforms of the even and odd samples,
+// E_k = (Z_k + conj(Z_(h-k))) / 2, O_k = (Z_k - conj(Z_(h-k))) / 2i and X_k = E_k + w^k O_k.
+// Going back, E_k = (X_k + conj(X_(h-k))) / 2 and O_k = (X_k - conj(X_(h-k))) w^-k / 2, because X_(k+h) = conj(X_(h-k)).
+#[derive(Debug, Clone)]
+struct RealTables<F> {
+    half: FftTables<F>,
+    // w^k for k in 0..=h, w = e^(2*pi*i/n)
+    twiddles: Vec<Complex<F>>
+}
+
+impl <F> RealTables<F>
+where F: FftFloat {
+    fn new(len:usize) -> RealTables<F> {
+        let half = len / 2;
+        let mut twiddles = twiddle_table(len);
+        twiddles.truncate(half + 1);
+        let half = match MixedRadix::new(half) {
+            Some(mixed) => FftTables::MixedRadix(mixed),
+            None => FftTables::Bluestein(Bluestein::new(half))
+        };
+        RealTables{half, twiddles}
+    }
+
+    // Packs values, zero filled to length n, into buf[..h] and transforms it.
+    fn forward(&self, values:&[F], buf:&mut [Complex<F>], threads:usize) {
+        let h = self.twiddles.len() - 1;
+        let get = |i:usize| values.get(i).copied().unwrap_or(F::zero());
+        for (j, z) in buf[..h].iter_mut().enumerate() {
+            *z = Complex::new(get(2 * j), get(2 * j + 1));
+        }
+        self.half.transform(&mut buf[..h], false, threads);
+    }
+
+    // X_m for m in 0..=h, from the packed transform z.
+    #[inline]
+    fn spectrum(&self, z:&[Complex<F>], m:usize) -> Complex<F> {
+        let h = z.len();
+        let a = z[m % h];
+        let b = z[(h - m) % h].conj();
+        let half = F::from_f64(0.5);
+        let even = (a + b) * half;
+        let odd = (a - b) * Complex::new(F::zero(), -half);
+        even + self.twiddles[m] * odd
+    }
+
+    // E_k + i O_k for the product spectrum R, from R_k and R_(h-k).
+    #[inline]
+    fn pack(&self, r_k:Complex<F>, r_hk:Complex<F>, k:usize) -> Complex<F> {
+        let b = r_hk.conj();
+        let half = F::from_f64(0.5);
+        let even = (r_k + b) * half;
+        let odd = (r_k - b) * self.twiddles[k].conj() * half;
+        even + Complex::<F>::i() * odd
+    }
+
+    // Multiplies the spectra behind the packed transforms p and q, transforms the product back and
+    // returns its first out_len values. Overwrites p.
+    fn multiply_inverse(&self, p:&mut [Complex<F>], q:&[Complex<F>], out_len:usize, threads:usize) -> Vec<F> {
+        let h = self.twiddles.len() - 1;
+        let (p, q) = (&mut p[..h], &q[..h]);
+        // k and h - k only depend on p[k] and p[h - k], so each pair can be overwritten in place
+        for k in 0..=h / 2 {
+            let r_k = self.spectrum(p, k) * self.spectrum(q, k);
+            let r_hk = self.spectrum(p, h - k) * self.spectrum(q, h - k);
+            p[k] = self.pack(r_k, r_hk, k);
+            if k > 0 && 2 * k != h {
+                p[h - k] = self.pack(r_hk, r_k, h - k);
+            }
+        }
+        self.half.transform(p, true, threads);
+        p.iter().flat_map(|z| [z.re, z.im]).take(out_len).collect()
+    }
+}
+
+impl RealTables<f64> {
+    // Like multiply_inverse, for the sum of the products of several pairs of packed transforms.
+    // Uses out as the buffer for the inverse transform.
+    fn sum_of_products_inverse(&self, pairs:&[(&[Complex64], &[Complex64])], out:&mut [Complex64], out_len:usize) -> Vec<f64> {
+        let h = self.twiddles.len() - 1;
+        let out = &mut out[..h];
+        for k in 0..=h / 2 {
+            let mut r_k = Complex64::zero();
+            let mut r_hk = Complex64::zero();
+            for (p, q) in pairs {
+                r_k += self.spectrum(p, k) * self.spectrum(q, k);
+                r_hk += self.spectrum(p, h - k) * self.spectrum(q, h - k);
+            }
+            out[k] = self.pack(r_k, r_hk, k);
+            if k > 0 && 2 * k != h {
+                out[h - k] = self.pack(r_hk, r_k, h - k);
+            }
+        }
+        self.half.transform(out, true, 1);
+        out.iter().flat_map(|z| [z.re, z.im]).take(out_len).collect()
+    }
+}
+
+impl <F> Bluestein<F>
+where F: FftFloat {
+    fn new(len:usize) -> Bluestein<F> {
+        let inner = MixedRadix::new(FftPlan::padded_len(2 * len - 1)).unwrap();
+        let m = inner.len;
+        // k^2 is only needed mod 2n, which keeps the angles small and accurate
+        let two_n = 2 * (len as u128);
+        let chirp:Vec<Complex<F>> = (0..len).map(|k| {
+            let k2 = ((k as u128) * (k as u128) % two_n) as f64;
+            let z = Complex64::from_polar(1., PI * k2 / (len as f64));
+            Complex::new(F::from_f64(z.re), F::from_f64(z.im))
+        }).collect();
+        // conj(chirp[k - j]) for k - j in -(n-1)..n, with the negative offsets wrapped to the end
+        let mut kernel = vec![Complex::zero(); m];
+        for (k, c) in chirp.iter().enumerate() {
+            kernel[k] = c.conj();
+            if k > 0 {
+                kernel[m - k] = c.conj();
+            }
+        }
+        inner.transform(&mut kernel, false, 1);
+        Bluestein{len, chirp, kernel, inner}
+    }
+
+    fn transform(&self, buf:&mut [Complex<F>], inverse:bool, threads:usize) {
+        // The inverse is the conjugate of the forward transform of the conjugate, divided by n.
+        if inverse {
+            buf.iter_mut().for_each(|z| *z = z.conj());
+        }
+        let mut work = vec![Complex::zero(); self.inner.len];
+        for ((w, x), c) in work.iter_mut().zip(buf.iter()).zip(self.chirp.iter()) {
+            *w = x * c;
+        }
+        self.inner.transform(&mut work, false, threads);
+        for (w, k) in work.iter_mut().zip(self.kernel.iter()) {
+            *w *= k;
+        }
+        self.inner.transform(&mut work, true, threads);
+        for ((x, w), c) in buf.iter_mut().zip(work.iter()).zip(self.chirp.iter()) {
+            *x = w * c;
+        }
+        if inverse {
+            let scale = F::from_f64(1. / (self.len as f64));
+            buf.iter_mut().for_each(|z| *z = z.conj() * scale);
+        }
+    }
+}
+
+const SIN_PI_3:f64 = 0.866_025_403_784_438_6; // sin(2*pi/3)
+const COS_2PI_5:f64 = 0.309_016_994_374_947_45;
+const COS_4PI_5:f64 = -0.809_016_994_374_947_5;
+const SIN_2PI_5:f64 = 0.951_056_516_295_153_5;
+const SIN_4PI_5:f64 = 0.587_785_252_292_473_1;
+
+// 3 point DFT, y_u = sum over t of x_t w^(t u) with w = e^(2*pi*i/3), or its conjugate for the inverse.
+#[inline]
+fn radix_3<F:FftFloat>(x:[Complex<F>; 3], inverse:bool) -> [Complex<F>; 3] {
+    let sin = F::from_f64(if inverse {-SIN_PI_3} else {SIN_PI_3});
+    let t = x[1] + x[2];
+    let d = (x[1] - x[2]) * Complex::new(F::zero(), sin);
+    let m = x[0] - t * F::from_f64(0.5);
+    [x[0] + t, m + d, m - d]
+}
+
+// 5 point DFT, same convention as radix_3. Pairs up x_t and x_(5-t), whose roots are conjugates.
+#[inline]
+fn radix_5<F:FftFloat>(x:[Complex<F>; 5], inverse:bool) -> [Complex<F>; 5] {
+    let (s1, s2) = if inverse {(-SIN_2PI_5, -SIN_4PI_5)} else {(SIN_2PI_5, SIN_4PI_5)};
+    let (s1, s2) = (F::from_f64(s1), F::from_f64(s2));
+    let (c1, c2) = (F::from_f64(COS_2PI_5), F::from_f64(COS_4PI_5));
+    let t1 = x[1] + x[4];
+    let t2 = x[2] + x[3];
+    let t3 = x[1] - x[4];
+    let t4 = x[2] - x[3];
+    let a1 = x[0] + t1 * c1 + t2 * c2;
+    let a2 = x[0] + t1 * c2 + t2 * c1;
+    let b1 = (t3 * s1 + t4 * s2) * Complex::i();
+    let b2 = (t3 * s2 - t4 * s1) * Complex::i();
+    [x[0] + t1 + t2, a1 + b1, a2 + b2, a2 - b2, a1 - b1]
+}
+
+/// w^k for k in 0..n, where w = e^(2*pi*i/n) is the principal n-th root of unity.
+/// Every entry is computed directly with cos and sin, so the table does not pick up
+/// the rounding error of repeatedly multiplying by w. Only angles up to pi/4 are evaluated and
+/// the rest follow by symmetry, which makes the quarter turns exact. The entries are computed in f64
+/// and then converted to F.
+fn twiddle_table<F:FftFloat>(n:usize) -> Vec<Complex<F>> {
+    (0..n).map(|k| {
+        // w^k = i^quadrant * e^(i*pi*r/2n) with 0 <= r < n
+        let quadrant = 4 * k / n;
+        let r = 4 * k - quadrant * n;
+        let z = if 2 * r <= n {
+            let angle = PI * (r as f64) / (2.0 * n as f64);
+            Complex64::new(angle.cos(), angle.sin())
+        } else {
+            // e^(i(pi/2 - a)) = i * conj(e^(ia))
+            let angle = PI * ((n - r) as f64) / (2.0 * n as f64);
+            Complex64::new(angle.sin(), angle.cos())
+        };
+        let z = match quadrant {
+            0 => z,
+            1 => Complex64::new(-z.im, z.re),
+            2 => -z,
+            _ => Complex64::new(z.im, -z.re)
+        };
+        Complex::new(F::from_f64(z.re), F::from_f64(z.im))
+    }).collect()
+}
+
+// The stage radices for a length n transform, 2s first. None if n has a prime factor other than 2, 3 and 5.
+fn fft_radices(mut n:usize) -> Option<Vec<usize>> {
+    if n == 0 {
+        return None
+    }
+    let mut radices = Vec::new();
+    for r in [2, 3, 5] {
+        while n.is_multiple_of(r) {
+            radices.push(r);
+            n /= r;
+        }
+    }
+    if n == 1 {Some(radices)} else {None}
+}
+
+// The input order the stages need: position pos of the permuted buffer holds input index source[pos].
+// The last stage combines r = radices.last() transforms of the inputs t, t + r, t + 2r, ..., each of
+// which sits in its own contiguous block, and so on recursively.
+fn digit_reversal(n:usize, radices:&[usize]) -> Vec<usize> {
+    match radices.split_last() {
+        None => vec![0],
+        Some((r, rest)) => {
+            let sub = digit_reversal(n / r, rest);
+            (0..*r).flat_map(|t| sub.iter().map(move |k| t + r * k)).collect()
+        }
+    }
+}
+
+// Breaks the digit reversal permutation into swaps, following each cycle of the permutation.
+fn digit_reversal_swaps(n:usize, radices:&[usize]) -> Vec<(usize, usize)> {
+    let source = digit_reversal(n, radices);
+    let mut visited = vec![false; n];
+    let mut swaps = Vec::new();
+    for start in 0..n {
+        let mut pos = start;
+        while !visited[pos] {
+            visited[pos] = true;
+            if !visited[source[pos]] {
+                swaps.push((pos, source[pos]));
+            }
+            pos = source[pos];
+        }
+    }
+    swaps
+}
+
+/// rev[i] is i with its log2(n) lowest bits reversed.
+fn bit_reversal_table(n:usize) -> Vec<usize> {
+    if n <= 2 {
+        return (0..n).collect()
+    }
+    let shift = usize::BITS - n.trailing_zeros();
+    (0..n).map(|i| i.reverse_bits() >> shift).collect()
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Karatsuba multiplication on coefficient slices.
+
+// returns the a.len() + b.len() - 1 coefficients of a * b. Both slices must be non-empty.
+fn karatsuba<T>(a:&[T], b:&[T], cutoff:usize) -> Vec<T> 
+    where T: Num + Copy
+{
+    // make a the longer operand
+    let (a, b) = if a.len() >= b.len() {(a, b)} else {(b, a)};
+    let mut out = vec![T::zero(); a.len() + b.len() - 1];
+    if b.len() < cutoff {
+        for (i, x) in a.iter().enumerate() {
+            for (j, y) in b.iter().enumerate() {
+                out[i + j] = out[i + j] + (*x) * (*y);
+            }
+        }
+    } else if 2 * b.len() <= a.len() {
+        // Unbalanced: splitting in half would leave b with an empty upper half.
+        // Cut a into b sized pieces and multiply each of them by b instead.
+        for (k, chunk) in a.chunks(b.len()).enumerate() {
+            add_at(&mut out, &karatsuba(chunk, b, cutoff), k * b.len());
+        }
+    } else {
+        // a = a0 + x^m a1, b = b0 + x^m b1. Here b.len() > m, so b1 is not empty.
+        let m = a.len() / 2;
+        let (a0, a1) = a.split_at(m);
+        let (b0, b1) = b.split_at(m);
+        let z0 = karatsuba(a0, b0, cutoff);
+        let z2 = karatsuba(a1, b1, cutoff);
+        // (a0 + a1)(b0 + b1) - z0 - z2 = a0 b1 + a1 b0. If nothing overflowed, subtracting coefficient
+        // by coefficient never goes below zero, so this is fine for unsigned T as well.
+        let mut z1 = karatsuba(&sum_slices(a0, a1), &sum_slices(b0, b1), cutoff);
+        for (i, z) in z0.iter().enumerate() {
+            z1[i] = z1[i] - *z;
+        }
+        for (i, z) in z2.iter().enumerate() {
+            z1[i] = z1[i] - *z;
+        }
+        add_at(&mut out, &z0, 0);
+        add_at(&mut out, &z2, 2 * m);
+        // the top coefficients of z1 are zero, and may run past the end of out
+        let fits = z1.len().min(out.len() - m);
+        add_at(&mut out, &z1[..fits], m);
+    }
+    out
+}
+
+// out[offset + i] += values[i], values that run past the end of out are dropped
+fn add_at<T>(out:&mut [T], values:&[T], offset:usize) 
+    where T: Num + Copy
+{
+    if offset >= out.len() {
+        return
+    }
+    for (o, v) in out[offset..].iter_mut().zip(values.iter()) {
+        *o = *o + *v;
+    }
+}
+
+// coefficients of a + b, as long as the longer of the two
+fn sum_slices<T>(a:&[T], b:&[T]) -> Vec<T> 
+    where T: Num + Copy
+{
+    let mut out = if a.len() >= b.len() {a.to_vec()} else {b.to_vec()};
+    let shorter = if a.len() >= b.len() {b} else {a};
+    add_at(&mut out, shorter, 0);
+    out
+}
+
+// coefficients of a - b, as long as the longer of the two
+fn diff_slices<T>(a:&[T], b:&[T]) -> Vec<T> 
+    where T: Num + Copy
+{
+    let mut out = a.to_vec();
+    out.resize(a.len().max(b.len()), T::zero());
+    for (o, v) in out.iter_mut().zip(b.iter()) {
+        *o = *o - *v;
+    }
+    out
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Toom-Cook 3-way multiplication on coefficient slices.
+
+// returns the a.len() + b.len() - 1 coefficients of a * b. Both slices must be non-empty.
+// Follows Bodrato's evaluation points 0, 1, -1, -2, infinity and his interpolation sequence.
+fn toom3<T>(a:&[T], b:&[T], cutoffs:&MulThresholds) -> Vec<T> 
+    where T: Num + Copy + Signed
+{
+    // make a the longer operand
+    let (a, b) = if a.len() >= b.len() {(a, b)} else {(b, a)};
+    if b.len() < cutoffs.toom3 {
+        return karatsuba(a, b, cutoffs.karatsuba)
+    }
+    let mut out = vec![T::zero(); a.len() + b.len() - 1];
+    if 3 * b.len() <= 2 * a.len() {
+        // Unbalanced: b would have too few coefficients to split into thirds next to a.
+        // Cut a into b sized pieces and multiply each of them by b instead.
+        for (k, chunk) in a.chunks(b.len()).enumerate() {
+            add_at(&mut out, &toom3(chunk, b, cutoffs), k * b.len());
+        }
+        return out
+    }
+    // a = a0 + x^k a1 + x^2k a2, same for b. An empty top third is padded with a zero.
+    let k = a.len().div_ceil(3);
+    let third = |s:&[T], i:usize| -> Vec<T> {
+        let start = (i * k).min(s.len());
+        let piece = &s[start..((i + 1) * k).min(s.len())];
+        if piece.is_empty() {vec![T::zero()]} else {piece.to_vec()}
+    };
+    let two = T::one() + T::one();
+    let three = two + T::one();
+    let scale = |s:&[T], c:T| -> Vec<T> {s.iter().map(|x| *x * c).collect()};
+    let divide = |s:&[T], c:T| -> Vec<T> {s.iter().map(|x| *x / c).collect()};
+    // values at 0, 1, -1, -2, infinity
+    let evaluate = |s:&[T]| -> [Vec<T>; 5] {
+        let (s0, s1, s2) = (third(s, 0), third(s, 1), third(s, 2));
+        let even = sum_slices(&s0, &s2);
+        let at_1 = sum_slices(&even, &s1);
+        let at_minus_1 = diff_slices(&even, &s1);
+        let at_minus_2 = diff_slices(&scale(&sum_slices(&at_minus_1, &s2), two), &s0);
+        [s0, at_1, at_minus_1, at_minus_2, s2]
+    };
+    let [a0, a_1, a_m1, a_m2, a_inf] = evaluate(a);
+    let [b0, b_1, b_m1, b_m2, b_inf] = evaluate(b);
+    let r0 = toom3(&a0, &b0, cutoffs);
+    let r_1 = toom3(&a_1, &b_1, cutoffs);
+    let r_m1 = toom3(&a_m1, &b_m1, cutoffs);
+    let r_m2 = toom3(&a_m2, &b_m2, cutoffs);
+    let r_inf = toom3(&a_inf, &b_inf, cutoffs);
+    // interpolation, every division here is exact
+    let r3 = divide(&diff_slices(&r_m2, &r_1), three);
+    let r1 = divide(&diff_slices(&r_1, &r_m1), two);
+    let r2 = diff_slices(&r_m1, &r0);
+    let r3 = sum_slices(&divide(&diff_slices(&r2, &r3), two), &scale(&r_inf, two));
+    let r2 = diff_slices(&sum_slices(&r2, &r1), &r_inf);
+    let r1 = diff_slices(&r1, &r3);
+    // the padding above can make some of these longer than they need to be, the extra coefficients are zero
+    for (i, r) in [r0, r1, r2, r3, r_inf].iter().enumerate() {
+        add_at(&mut out, r, i * k);
+    }
+    out
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Number theoretic transform: the FFT over Z/pZ instead of the complex numbers. There is no rounding,
+// so products of integer polynomials come out exact as long as their coefficients fit below p.
+
+/// A prime p = c * 2^k + 1 together with a generator of (Z/pZ)*. Z/pZ then has a primitive 2^j-th root
+/// of unity for every j <= k, which is all the NTT needs for lengths up to 2^k.
+pub trait NttPrime {
+    const MODULUS: u64;
+    const PRIMITIVE_ROOT: u64;
+    /// k in p = c * 2^k + 1
+    const TWO_ADICITY: u32;
+}
+
+/// 998244353 = 119 * 2^23 + 1, the usual NTT prime. Supports lengths up to 2^23.
+#[derive(Debug, Clone, Copy)]
+pub struct P998244353;
+
+impl NttPrime for P998244353 {
+    const MODULUS: u64 = 998_244_353;
+    const PRIMITIVE_ROOT: u64 = 3;
+    const TWO_ADICITY: u32 = 23;
+}
+
+/// 2^64 - 2^32 + 1. Large enough for products of most i64 polynomials, and supports lengths up to 2^32.
+#[derive(Debug, Clone, Copy)]
+pub struct Goldilocks;
+
+impl NttPrime for Goldilocks {
+    const MODULUS: u64 = 0xFFFF_FFFF_0000_0001;
+    const PRIMITIVE_ROOT: u64 = 7;
+    const TWO_ADICITY: u32 = 32;
+}
+
+/// Evaluates the polynomial with coefficients buf (mod P) at the powers of w, a primitive n-th root of unity mod P.
+/// The counterpart of FftPlan::forward. Every entry must already be reduced mod P.
+/// buf.len() must be a power of 2 that is at most 2^P::TWO_ADICITY.
+pub fn ntt<P:NttPrime>(buf:&mut [u64]) {
+    ntt_u64::<P>(buf, false);
+}
+
+/// Inverse of ntt, in place. The result is already divided by n.
+pub fn inverse_ntt<P:NttPrime>(buf:&mut [u64]) {
+    ntt_u64::<P>(buf, true);
+}
+
+#[inline]
+fn mul_mod(a:u64, b:u64, m:u64) -> u64 {
+    ((a as u128 * b as u128) % m as u128) as u64
+}
+
+#[inline]
+fn add_mod(a:u64, b:u64, m:u64) -> u64 {
+    let (sum, overflow) = a.overflowing_add(b);
+    if overflow || sum >= m {sum.wrapping_sub(m)} else {sum}
+}
+
+#[inline]
+fn sub_mod(a:u64, b:u64, m:u64) -> u64 {
+    if a >= b {a - b} else {a.wrapping_sub(b).wrapping_add(m)}
+}
+
+fn pow_mod(mut base:u64, mut exp:u64, m:u64) -> u64 {
+    let mut result = 1;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+// The public ntt takes plain residues, the transform itself works on ModInt.
+fn ntt_u64<P:NttPrime>(buf:&mut [u64], inverse:bool) {
+    let mut values:Vec<ModInt<P>> = buf.iter().map(|x| ModInt{value: *x, prime: PhantomData}).collect();
+    ntt_in_place(&mut values, inverse);
+    for (x, v) in buf.iter_mut().zip(values.iter()) {
+        *x = v.value;
+    }
+}
+
+thread_local! {
+    // w^k for k < n/2, where w is the primitive n-th root of unity and n the longest length transformed so far,
+    // keyed by the modulus. A transform of length m <= n uses every (n/m)-th entry, so one table per prime is enough.
+    static NTT_TWIDDLES: RefCell<HashMap<u64, Vec<u64>>> = RefCell::new(HashMap::new());
+}
+
+// Runs f with this thread's twiddle table for P, extended to length n first if it is shorter.
+fn with_ntt_twiddles<P:NttPrime, R>(n:usize, f:impl FnOnce(&[u64]) -> R) -> R {
+    NTT_TWIDDLES.with(|tables| {
+        let mut tables = tables.borrow_mut();
+        let table = tables.entry(P::MODULUS).or_default();
+        if 2 * table.len() < n {
+            let w = pow_mod(P::PRIMITIVE_ROOT, (P::MODULUS - 1) >> n.trailing_zeros(), P::MODULUS);
+            table.clear();
+            table.push(1);
+            for k in 1..n / 2 {
+                table.push(mul_mod(table[k - 1], w, P::MODULUS));
+            }
+        }
+        f(table)
+    })
+}
+
+// ring_fft for ModInt<P>, with the twiddle factors from the table of with_ntt_twiddles instead of
+// recomputing them on every call.
+fn ntt_in_place<P:NttPrime>(buf:&mut [ModInt<P>], inverse:bool) {
+    let n = buf.len();
+    assert!(n.is_power_of_two() && n.trailing_zeros() <= P::TWO_ADICITY, "NTT length must be a power of 2 up to 2^{}, got {}.", P::TWO_ADICITY, n);
+    for (i, j) in bit_reversal_table(n).into_iter().enumerate() {
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+    with_ntt_twiddles::<P, _>(n, |twiddles| {
+        let mut half = 1;
+        while half < n {
+            // the table holds the powers of a root of order 2 * twiddles.len(), this stage needs one of order 2 * half
+            let stride = twiddles.len() / half;
+            for block in buf.chunks_exact_mut(half << 1) {
+                let (lo, hi) = block.split_at_mut(half);
+                for j in 0..half {
+                    let odd_term = ModInt{value: mul_mod(twiddles[j * stride], hi[j].value, P::MODULUS), prime: PhantomData};
+                    hi[j] = lo[j] - odd_term;
+                    lo[j] = lo[j] + odd_term;
+                }
+            }
+            half <<= 1;
+        }
+    });
+    // same as in ring_fft_in_place
+    if inverse {
+        let n_inv = ModInt::<P>::new(n as u64).inverse().expect("n is below P, so it has an inverse.");
+        buf[1..].reverse();
+        buf.iter_mut().for_each(|x| *x = *x * n_inv);
+    }
+}
+
+// Cyclic convolution of a and b mod P, with the output length padded to a power of 2.
+fn ntt_convolve<P:NttPrime>(a:&[ModInt<P>], b:&[ModInt<P>]) -> Result<Vec<ModInt<P>>, PolynomialError> {
+    let out_len = a.len() + b.len() - 1;
+    let target_len = out_len.next_power_of_two();
+    if target_len.trailing_zeros() > P::TWO_ADICITY {
+        return Err(PolynomialError::TransformTooLong)
+    }
+    let mut a_buf = a.to_vec();
+    a_buf.resize(target_len, ModInt::zero());
+    ntt_in_place(&mut a_buf, false);
+    // squaring passes the same slice twice, which only needs one forward transform
+    if std::ptr::eq(a, b) {
+        a_buf.iter_mut().for_each(|x| *x = *x * *x);
+    } else {
+        let mut b_buf = b.to_vec();
+        b_buf.resize(target_len, ModInt::zero());
+        ntt_in_place(&mut b_buf, false);
+        for (x, y) in a_buf.iter_mut().zip(b_buf.iter()) {
+            *x = *x * *y;
+        }
+    }
+    ntt_in_place(&mut a_buf, true);
+    a_buf.truncate(out_len);
+    Ok(a_buf)
+}
+
+// Largest absolute value a coefficient of a * b can have: |c_k| <= sum |a_i| |b_(k-i)|, which is at most
+// both ||a||_1 ||b||_inf and ||a||_inf ||b||_1. Saturates instead of overflowing.
+// a and b hold the absolute values of the coefficients.
+fn product_coeff_bound(a:&[u128], b:&[u128]) -> u128 {
+    let l1 = |v:&[u128]| v.iter().fold(0u128, |acc, x| acc.saturating_add(*x));
+    let inf = |v:&[u128]| v.iter().fold(0u128, |acc, x| acc.max(*x));
+    l1(a).saturating_mul(inf(b)).min(inf(a).saturating_mul(l1(b)))
+}
+
+impl Polynomial<i64> {
+    /// Exact product of self and q through the NTT mod 2^64 - 2^32 + 1.
+    /// 
+    /// returns: the product, or Err(CoefficientOverflow) when a coefficient of the product could be
+    /// too large in absolute value to be told apart mod p, i.e. larger than (p - 1)/2.
+    #[inline]
+    pub fn ntt_mul(&self, q:&Polynomial<i64>) -> Result<Polynomial<i64>, PolynomialError> {
+        self.ntt_mul_with::<Goldilocks>(q)
+    }
+
+    /// Same as ntt_mul, over the prime P. The coefficients of the product must stay within (P - 1)/2 in
+    /// absolute value, so smaller primes only suit products with small coefficients.
+    pub fn ntt_mul_with<P:NttPrime>(&self, q:&Polynomial<i64>) -> Result<Polynomial<i64>, PolynomialError> {
+        let abs = |p:&Polynomial<i64>| p.coeffs.iter().map(|x| x.unsigned_abs() as u128).collect::<Vec<u128>>();
+        let bound = product_coeff_bound(&abs(self), &abs(q));
+        let half = (P::MODULUS - 1) / 2;
+        if bound > half as u128 {
+            return Err(PolynomialError::CoefficientOverflow)
+        }
+        let reduce = |p:&Polynomial<i64>| p.coeffs.iter().map(|x| ModInt::<P>::from_i64(*x)).collect::<Vec<_>>();
+        let a = reduce(self);
+        let product = if std::ptr::eq(self, q) {ntt_convolve(&a, &a)?} else {ntt_convolve(&a, &reduce(q))?};
+        // residues above (p - 1)/2 stand for negative numbers
+        Ok(Polynomial::no_leading_zeros(product.into_iter().map(|x| {
+            if x.value > half {(x.value as i128 - P::MODULUS as i128) as i64} else {x.value as i64}
+        }).collect()))
+    }
+}
+
+impl Polynomial<u64> {
+    /// Exact product of self and q through the NTT mod 2^64 - 2^32 + 1.
+    /// 
+    /// returns: the product, or Err(CoefficientOverflow) when a coefficient of the product could be p or larger.
+    #[inline]
+    pub fn ntt_mul(&self, q:&Polynomial<u64>) -> Result<Polynomial<u64>, PolynomialError> {
+        self.ntt_mul_with::<Goldilocks>(q)
+    }
+
+    /// Same as ntt_mul, over the prime P.
+    pub fn ntt_mul_with<P:NttPrime>(&self, q:&Polynomial<u64>) -> Result<Polynomial<u64>, PolynomialError> {
+        let widen = |p:&Polynomial<u64>| p.coeffs.iter().map(|x| *x as u128).collect::<Vec<u128>>();
+        let bound = product_coeff_bound(&widen(self), &widen(q));
+        if bound >= P::MODULUS as u128 {
+            return Err(PolynomialError::CoefficientOverflow)
+        }
+        let reduce = |p:&Polynomial<u64>| p.coeffs.iter().map(|x| ModInt::<P>::new(*x)).collect::<Vec<_>>();
+        let a = reduce(self);
+        let product = if std::ptr::eq(self, q) {ntt_convolve(&a, &a)?} else {ntt_convolve(&a, &reduce(q))?};
+        Ok(Polynomial::no_leading_zeros(product.into_iter().map(|x| x.value).collect()))
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Multi-modular multiplication: NTTs modulo several primes, recombined with the Chinese Remainder Theorem.
+// Only into i128: there is no big integer coefficient type, since Polynomial needs Copy coefficients.
+
+/// 8388606 * 2^40 + 1, just below 2^63. Supports lengths up to 2^40.
+#[derive(Debug, Clone, Copy)]
+pub struct P9223369837831520257;
+
+impl NttPrime for P9223369837831520257 {
+    const MODULUS: u64 = 0x7FFF_FE00_0000_0001;
+    const PRIMITIVE_ROOT: u64 = 7;
+    const TWO_ADICITY: u32 = 40;
+}
+
+/// 8388591 * 2^40 + 1, just below 2^63. Supports lengths up to 2^40.
+#[derive(Debug, Clone, Copy)]
+pub struct P9223353345157103617;
+
+impl NttPrime for P9223353345157103617 {
+    const MODULUS: u64 = 0x7FFF_EF00_0000_0001;
+    const PRIMITIVE_ROOT: u64 = 5;
+    const TWO_ADICITY: u32 = 40;
+}
+
+// Number of bits needed for the absolute value of x.
+#[inline]
+fn bit_length(x:u128) -> u32 {
+    u128::BITS - x.leading_zeros()
+}
+
+// Residues of a * b modulo P, where a and b are given by their residues modulo P.
+fn crt_residues<P:NttPrime>(a:&[i128], b:&[i128]) -> Result<(u64, Vec<u64>), PolynomialError> {
+    let reduce = |v:&[i128]| v.iter().map(|x| ModInt::<P>{value: x.rem_euclid(P::MODULUS as i128) as u64, prime: PhantomData}).collect::<Vec<_>>();
+    let a_reduced = reduce(a);
+    let product = if std::ptr::eq(a, b) {ntt_convolve(&a_reduced, &a_reduced)?} else {ntt_convolve(&a_reduced, &reduce(b))?};
+    Ok((P::MODULUS, product.into_iter().map(|x| x.value).collect()))
+}
+
+// Garner's algorithm: the x in [0, m_0 * m_1 * ...) with x = residues[i] mod moduli[i].
+// The caller guarantees that x is below 2^128.
+fn garner(residues:&[u64], moduli:&[u64]) -> u128 {
+    let mut digits:Vec<u64> = Vec::with_capacity(moduli.len());
+    for (i, m) in moduli.iter().enumerate() {
+        // value of the digits so far, and the product of the moduli before i, both mod m
+        let mut value = 0;
+        let mut radix = 1;
+        for (d, m_j) in digits.iter().zip(moduli.iter()) {
+            value = add_mod(value, mul_mod(*d, radix, *m), *m);
+            radix = mul_mod(radix, *m_j % *m, *m);
+        }
+        let diff = sub_mod(residues[i] % *m, value, *m);
+        digits.push(mul_mod(diff, pow_mod(radix, *m - 2, *m), *m));
+    }
+    let mut x:u128 = 0;
+    let mut radix:u128 = 1;
+    for (d, m) in digits.iter().zip(moduli.iter()) {
+        x += (*d as u128) * radix;
+        radix = radix.wrapping_mul(*m as u128);
+    }
+    x
+}
+
+impl Polynomial<i128> {
+    /// Exact product of self and q. The product is computed with NTTs modulo up to three primes
+    /// and put back together with the Chinese Remainder Theorem. The number of primes is picked
+    /// from a bound on the coefficients of the product, so small inputs only pay for one NTT.
+    /// 
+    /// This is the only CRT product: it is for i128 coefficients, and products that do not fit in
+    /// an i128 are an error, not a big integer result.
+    /// 
+    /// returns: the product, or Err(CoefficientOverflow) when the bit lengths of max|self|, max|q| and
+    /// min(self.len(), q.len()) - 1 add up to more than 127, so a coefficient of the product might not fit.
+    pub fn crt_mul(&self, q:&Polynomial<i128>) -> Result<Polynomial<i128>, PolynomialError> {
+        let a = self.coeffs.as_slice().unwrap();
+        let b = q.coeffs.as_slice().unwrap();
+        // |c_k| <= max|a| * max|b| * (number of terms in the sum), which is below 2^bound_bits
+        let inf = |v:&[i128]| v.iter().map(|x| x.unsigned_abs()).max().unwrap_or(0);
+        let terms = a.len().min(b.len()) as u128;
+        let bound_bits = bit_length(inf(a)) + bit_length(inf(b)) + bit_length(terms - 1);
+        if bound_bits > 127 {
+            return Err(PolynomialError::CoefficientOverflow)
+        }
+        // Shifting every coefficient by 2^bound_bits makes it non-negative and below 2^(bound_bits + 1).
+        // The primes multiply to more than that, so the CRT gives back the shifted coefficient exactly.
+        let mut parts = vec![crt_residues::<Goldilocks>(a, b)?];
+        if bound_bits + 1 > 63 {
+            parts.push(crt_residues::<P9223369837831520257>(a, b)?);
+        }
+        if bound_bits + 1 > 125 {
+            parts.push(crt_residues::<P9223353345157103617>(a, b)?);
+        }
+        let moduli:Vec<u64> = parts.iter().map(|(m, _)| *m).collect();
+        let shift:u128 = 1 << bound_bits;
+        let shifts:Vec<u64> = moduli.iter().map(|m| (shift % (*m as u128)) as u64).collect();
+        let product = (0..parts[0].1.len()).map(|k| {
+            let residues:Vec<u64> = parts.iter().zip(shifts.iter())
+                .map(|((m, r), s)| add_mod(r[k], *s, *m)).collect();
+            garner(&residues, &moduli).wrapping_sub(shift) as i128
+        }).collect();
+        Ok(Polynomial::no_leading_zeros(product))
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Exact products of integer polynomials with f64 FFTs. The coefficients are cut into limbs that are small
+// enough for every convolution of two limbs to round to the right integer, by convolution_error.
+
+// Limb widths fft_mul_exact tries, widest first. Narrower limbs need more transforms, but have smaller errors.
+const LIMB_BITS:[u32; 4] = [16, 12, 8, 4];
+
+// Splits the coefficients into count limbs of the given width: c = sum over l of limbs[l] 2^(bits * l),
+// with every limb in [-2^(bits-1), 2^(bits-1)). Limbs centered at 0 are half as large as unsigned ones.
+fn split_limbs(coeffs:&[i64], bits:u32, count:usize) -> Vec<Vec<f64>> {
+    let mut limbs = vec![vec![0.; coeffs.len()]; count];
+    let base = 1i128 << bits;
+    for (i, c) in coeffs.iter().enumerate() {
+        let mut rest = *c as i128;
+        for limb in limbs.iter_mut() {
+            let mut digit = rest.rem_euclid(base);
+            if 2 * digit >= base {
+                digit -= base;
+            }
+            limb[i] = digit as f64;
+            rest = (rest - digit) >> bits;
+        }
+    }
+    limbs
+}
+
+impl Polynomial<i64> {
+    /// Exact product of self and q with f64 FFTs. The coefficients are split into limbs of at most 16 bits.
+    /// Each limb is transformed once, the limb products that land on the same power of 2 are summed up in
+    /// the transformed domain, and every sum is transformed back, rounded and carried into the result.
+    /// The limbs are narrowed until the error bound of fft_mul_error_bound guarantees correct rounding.
+    /// 
+    /// returns: the product, Err(CoefficientOverflow) when a coefficient of the product does not fit
+    /// in an i64, or Err(PrecisionLoss) when even 4 bit limbs are not safe, which takes inputs far
+    /// longer than fit in memory.
+    pub fn fft_mul_exact(&self, q:&Polynomial<i64>) -> Result<Polynomial<i64>, PolynomialError> {
+        let a = self.coeffs.as_slice().unwrap();
+        let b = q.coeffs.as_slice().unwrap();
+        let bits = |v:&[i64]| bit_length(v.iter().map(|x| x.unsigned_abs()).max().unwrap_or(0) as u128);
+        let (a_bits, b_bits) = (bits(a), bits(b));
+        if a_bits == 0 || b_bits == 0 {
+            return Ok(Polynomial::zero())
+        }
+        let out_len = a.len() + b.len() - 1;
+        let n = FftPlan::padded_len(out_len);
+        let norm = |limb:&Vec<f64>| limb.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let (limb_bits, a_limbs, b_limbs) = LIMB_BITS.iter().find_map(|limb_bits| {
+            // centered limbs need one bit more than the coefficients have
+            let a_limbs = split_limbs(a, *limb_bits, (a_bits + 1).div_ceil(*limb_bits) as usize);
+            let b_limbs = split_limbs(b, *limb_bits, (b_bits + 1).div_ceil(*limb_bits) as usize);
+            let a_norms:Vec<f64> = a_limbs.iter().map(norm).collect();
+            let b_norms:Vec<f64> = b_limbs.iter().map(norm).collect();
+            let safe = (0..a_limbs.len() + b_limbs.len() - 1).all(|s| {
+                let pairs = limb_pairs(s, a_limbs.len(), b_limbs.len());
+                let norms:f64 = pairs.iter().map(|(l, m)| a_norms[*l] * b_norms[*m]).sum();
+                norms * convolution_error::<f64>(n, pairs.len()) < 0.5
+            });
+            safe.then_some((*limb_bits, a_limbs, b_limbs))
+        }).ok_or(PolynomialError::PrecisionLoss)?;
+        // sums[s][k] is the coefficient of x^k in the sum of the limb products a_l * b_m with l + m = s
+        let sums:Vec<Vec<f64>> = FftPlan::with_cached(n, |plan| {
+            // padded lengths are even, so the plan has real tables
+            let real = plan.real.as_ref().unwrap();
+            let transform = |limb:&Vec<f64>| {
+                let mut buf = vec![Complex64::zero(); n / 2];
+                real.forward(limb, &mut buf, 1);
+                buf
+            };
+            let a_spectra:Vec<Vec<Complex64>> = a_limbs.iter().map(transform).collect();
+            let b_spectra:Vec<Vec<Complex64>> = b_limbs.iter().map(transform).collect();
+            let mut out = vec![Complex64::zero(); n / 2];
+            (0..a_limbs.len() + b_limbs.len() - 1).map(|s| {
+                let pairs:Vec<(&[Complex64], &[Complex64])> = limb_pairs(s, a_limbs.len(), b_limbs.len()).into_iter()
+                    .map(|(l, m)| (a_spectra[l].as_slice(), b_spectra[m].as_slice())).collect();
+                real.sum_of_products_inverse(&pairs, &mut out, out_len)
+            }).collect()
+        });
+        // c_k = sum over s of round(sums[s][k]) 2^(limb_bits * s), evaluated from the top like a polynomial in 2^limb_bits.
+        // If c_k fits in an i64, every partial result does too, up to the size of one limb sum, so i128 cannot
+        // overflow on the way.
+        let product:Option<Vec<i64>> = (0..out_len).map(|k| {
+            sums.iter().rev().try_fold(0i128, |acc, sum| {
+                acc.checked_mul(1 << limb_bits)?.checked_add(sum[k].round() as i128)
+            }).and_then(|c| i64::try_from(c).ok())
+        }).collect();
+        product.map(Polynomial::no_leading_zeros).ok_or(PolynomialError::CoefficientOverflow)
+    }
+}
+
+// The pairs (l, m) with l + m = s, l < a_count and m < b_count.
+fn limb_pairs(s:usize, a_count:usize, b_count:usize) -> Vec<(usize, usize)> {
+    (s.saturating_sub(b_count - 1)..a_count.min(s + 1)).map(|l| (l, s - l)).collect()
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Radix 2 Cooley-Tukey FFT over any coefficient ring that has the roots of unity for it: the complex
+// numbers, Z/pZ for the NTT primes, or finite field extensions and other rings that implement RootOfUnity.
+// The ring only needs +, - and *, the transform never divides.
+
+/// A coefficient ring with primitive 2^k-th roots of unity, for the radix 2 FFT ring_fft.
+/// 
+/// For a finite field or another ring of your own, implement this next to Num and call ring_fft or
+/// ring_fft_mul. The transform evaluates at the powers of root_of_unity(k), so the inverse also needs
+/// 1/n, which inverse_of provides.
+pub trait RootOfUnity: Num + Clone + Copy + Display {
+    /// A primitive 2^log_n-th root of unity w, i.e. w^(2^log_n) = 1 and w^(2^(log_n - 1)) != 1.
+    /// None if the ring has none.
+    fn root_of_unity(log_n:u32) -> Option<Self>;
+    /// 1/n, or None if n has no inverse in the ring.
+    fn inverse_of(n:usize) -> Option<Self>;
+}
+
+impl <F> RootOfUnity for Complex<F>
+where F: FftFloat {
+    /// e^(2*pi*i/2^log_n), the same root as FftPlan::forward uses.
+    fn root_of_unity(log_n:u32) -> Option<Complex<F>> {
+        let z = match log_n {
+            // exact at the quarter turns
+            0 => Complex64::new(1., 0.),
+            1 => Complex64::new(-1., 0.),
+            2 => Complex64::i(),
+            _ => Complex64::from_polar(1., 2. * PI / 2f64.powi(log_n as i32))
+        };
+        Some(Complex::new(F::from_f64(z.re), F::from_f64(z.im)))
+    }
+
+    fn inverse_of(n:usize) -> Option<Complex<F>> {
+        Some(Complex::new(F::from_f64(1. / (n as f64)), F::zero()))
+    }
+}
+
+/// An element of Z/PZ for one of the NTT primes. Polynomial<ModInt<P>> is a polynomial over the field
+/// with P::MODULUS elements, and can be multiplied with ring_fft_mul.
+pub struct ModInt<P> {
+    value: u64,
+    prime: PhantomData<P>
+}
+
+impl <P> ModInt<P>
+where P: NttPrime {
+    /// x mod P.
+    pub fn new(x:u64) -> ModInt<P> {
+        ModInt{value: x % P::MODULUS, prime: PhantomData}
+    }
+
+    /// x mod P, for negative x as well.
+    pub fn from_i64(x:i64) -> ModInt<P> {
+        ModInt::new((x as i128).rem_euclid(P::MODULUS as i128) as u64)
+    }
+
+    /// The representative in 0..P::MODULUS.
+    #[inline]
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    pub fn pow(&self, exp:u64) -> ModInt<P> {
+        ModInt::new(pow_mod(self.value, exp, P::MODULUS))
+    }
+
+    /// The multiplicative inverse, None for 0.
+    pub fn inverse(&self) -> Option<ModInt<P>> {
+        if self.value == 0 {None} else {Some(self.pow(P::MODULUS - 2))}
+    }
+}
+
+// Written out instead of derived, so that P does not have to implement these traits too.
+impl <P> Clone for ModInt<P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl <P> Copy for ModInt<P> {}
+
+impl <P> PartialEq for ModInt<P> {
+    fn eq(&self, other:&Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl <P> Eq for ModInt<P> {}
+
+impl <P> std::fmt::Debug for ModInt<P> {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ModInt({})", self.value)
+    }
+}
+
+impl <P> Display for ModInt<P> {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl <P:NttPrime> Add for ModInt<P> {
+    type Output = ModInt<P>;
+    fn add(self, other:ModInt<P>) -> ModInt<P> {
+        ModInt{value: add_mod(self.value, other.value, P::MODULUS), prime: PhantomData}
+    }
+}
+
+impl <P:NttPrime> Sub for ModInt<P> {
+    type Output = ModInt<P>;
+    fn sub(self, other:ModInt<P>) -> ModInt<P> {
+        ModInt{value: sub_mod(self.value, other.value, P::MODULUS), prime: PhantomData}
+    }
+}
+
+impl <P:NttPrime> Mul for ModInt<P> {
+    type Output = ModInt<P>;
+    fn mul(self, other:ModInt<P>) -> ModInt<P> {
+        ModInt{value: mul_mod(self.value, other.value, P::MODULUS), prime: PhantomData}
+    }
+}
+
+impl <P:NttPrime> Div for ModInt<P> {
+    type Output = ModInt<P>;
+    fn div(self, other:ModInt<P>) -> ModInt<P> {
+        let inverse = other.inverse().expect("attempt to divide by zero");
+        ModInt{value: mul_mod(self.value, inverse.value, P::MODULUS), prime: PhantomData}
+    }
+}
+
+// Every non-zero element divides every other one, so the remainder is always 0.
+impl <P:NttPrime> Rem for ModInt<P> {
+    type Output = ModInt<P>;
+    fn rem(self, other:ModInt<P>) -> ModInt<P> {
+        assert!(!other.is_zero(), "attempt to calculate the remainder with a divisor of zero");
+        ModInt::zero()
+    }
+}
+
+impl <P:NttPrime> Neg for ModInt<P> {
+    type Output = ModInt<P>;
+    fn neg(self) -> ModInt<P> {
+        ModInt::zero() - self
+    }
+}
+
+impl <P:NttPrime> Zero for ModInt<P> {
+    fn zero() -> ModInt<P> {
+        ModInt{value: 0, prime: PhantomData}
+    }
+
+    fn is_zero(&self) -> bool {
+        self.value == 0
+    }
+}
+
+impl <P:NttPrime> One for ModInt<P> {
+    fn one() -> ModInt<P> {
+        ModInt{value: 1, prime: PhantomData}
+    }
+}
+
+impl <P:NttPrime> Num for ModInt<P> {
+    type FromStrRadixErr = std::num::ParseIntError;
+
+    fn from_str_radix(s:&str, radix:u32) -> Result<ModInt<P>, std::num::ParseIntError> {
+        u64::from_str_radix(s, radix).map(ModInt::new)
+    }
+}
+
+impl <P:NttPrime> RootOfUnity for ModInt<P> {
+    /// PRIMITIVE_ROOT^((p - 1)/2^log_n), the same root as ntt uses.
+    fn root_of_unity(log_n:u32) -> Option<ModInt<P>> {
+        if log_n > P::TWO_ADICITY {
+            return None
+        }
+        Some(ModInt::new(P::PRIMITIVE_ROOT).pow((P::MODULUS - 1) >> log_n))
+    }
+
+    fn inverse_of(n:usize) -> Option<ModInt<P>> {
+        ModInt::new(n as u64).inverse()
+    }
+}
+
+impl <P:NttPrime> MulDispatch for ModInt<P> {
+    fn mul_algorithm(p_len:usize, q_len:usize, thresholds:&MulThresholds) -> MulAlgorithm {
+        let shorter = p_len.min(q_len);
+        if shorter >= thresholds.ntt {
+            MulAlgorithm::Ntt
+        } else if shorter >= thresholds.karatsuba {
+            MulAlgorithm::Karatsuba
+        } else {
+            MulAlgorithm::Schoolbook
+        }
+    }
+
+    fn mul_using(p:&Polynomial<ModInt<P>>, q:&Polynomial<ModInt<P>>, algorithm:MulAlgorithm, thresholds:&MulThresholds) -> Polynomial<ModInt<P>> {
+        match algorithm {
+            MulAlgorithm::Karatsuba => p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba),
+            MulAlgorithm::Ntt => ntt_convolve(p.coeffs.as_slice().unwrap(), q.coeffs.as_slice().unwrap())
+                .map(Polynomial::no_leading_zeros)
+                .unwrap_or_else(|_| p.karatsuba_mul_with_cutoff(q, thresholds.karatsuba)),
+            _ => p.multiply(q)
+        }
+    }
+}
+
+/// Evaluates the polynomial with coefficients buf at the powers of T::root_of_unity(log2(n)), in place.
+/// buf.len() must be a power of 2.
+/// 
+/// returns: Err(TransformTooLong) if T has no root of unity of order buf.len().
+pub fn ring_fft<T:RootOfUnity>(buf:&mut [T]) -> Result<(), PolynomialError> {
+    ring_fft_in_place(buf, false)
+}
+
+/// Inverse of ring_fft, in place. The result is already divided by n.
+/// 
+/// returns: Err(TransformTooLong) if T has no root of unity of order buf.len(), or if n has no inverse in T.
+pub fn inverse_ring_fft<T:RootOfUnity>(buf:&mut [T]) -> Result<(), PolynomialError> {
+    ring_fft_in_place(buf, true)
+}
+
+fn ring_fft_in_place<T:RootOfUnity>(buf:&mut [T], inverse:bool) -> Result<(), PolynomialError> {
+    let n = buf.len();
+    assert!(n.is_power_of_two(), "ring_fft length must be a power of 2, got {}.", n);
+    let log_n = n.trailing_zeros();
+    T::root_of_unity(log_n).ok_or(PolynomialError::TransformTooLong)?;
+    // w^k for k < n/2, as the product of the roots w^(2^b) for the bits b of k. Every entry is a product of
+    // at most log2(n) roots that come straight from T, so inexact rings do not pile up rounding errors.
+    let mut twiddles = Vec::with_capacity(n / 2);
+    twiddles.push(T::one());
+    for b in 0..log_n.saturating_sub(1) {
+        let root = T::root_of_unity(log_n - b).ok_or(PolynomialError::TransformTooLong)?;
+        for j in 0..twiddles.len() {
+            twiddles.push(twiddles[j] * root);
+        }
+    }
+    let n_inv = if inverse {Some(T::inverse_of(n).ok_or(PolynomialError::TransformTooLong)?)} else {None};
+    for (i, j) in bit_reversal_table(n).into_iter().enumerate() {
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+    let mut half = 1;
+    while half < n {
+        let stride = n / (half << 1);
+        for block in buf.chunks_exact_mut(half << 1) {
+            let (lo, hi) = block.split_at_mut(half);
+            for j in 0..half {
+                let odd_term = twiddles[j * stride] * hi[j];
+                hi[j] = lo[j] - odd_term;
+                lo[j] = lo[j] + odd_term;
+            }
+        }
+        half <<= 1;
+    }
+    // The inverse transform at w is the forward transform at w^-1 = w^(n-1), divided by n. Evaluating at
+    // w^-k gives the entry n - k of the forward transform, so it is enough to reverse the order.
+    if let Some(n_inv) = n_inv {
+        buf[1..].reverse();
+        buf.iter_mut().for_each(|x| *x = *x * n_inv);
+    }
+    Ok(())
+}
+
+impl <T> Polynomial<T>
+where T: RootOfUnity {
+    /// Product of self and q through ring_fft, padded to a power of 2. Exact for exact rings like ModInt.
+    /// For f64 and f32 coefficients, fft_mul is faster.
+    /// 
+    /// returns: the product, or Err(TransformTooLong) if T lacks the roots of unity for the padded length.
+    pub fn ring_fft_mul(&self, q:&Polynomial<T>) -> Result<Polynomial<T>, PolynomialError> {
+        if q.deg() == 0 || self.deg() == 0 {
+            return Ok(self.multiply(q))
+        }
+        let out_len = self.deg() + q.deg() + 1;
+        let target_len = out_len.next_power_of_two();
+        let mut p_buf = self.coeffs.to_vec();
+        p_buf.resize(target_len, T::zero());
+        ring_fft(&mut p_buf)?;
+        // squaring only needs one forward transform
+        if std::ptr::eq(self, q) {
+            p_buf.iter_mut().for_each(|a| *a = *a * *a);
+        } else {
+            let mut q_buf = q.coeffs.to_vec();
+            q_buf.resize(target_len, T::zero());
+            ring_fft(&mut q_buf)?;
+            for (a, b) in p_buf.iter_mut().zip(q_buf.iter()) {
+                *a = *a * *b;
+            }
+        }
+        inverse_ring_fft(&mut p_buf)?;
+        p_buf.truncate(out_len);
+        Ok(Polynomial::no_leading_zeros(p_buf))
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Convolution and correlation of plain arrays, for signals rather than polynomials. Unlike a product of
+// polynomials, the output keeps its length: trailing zeros are part of a signal.
+
+/// Which part of the full convolution convolve and correlate return, as in numpy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConvolveMode {
+    /// Every term where the two inputs overlap, a.len() + b.len() - 1 of them.
+    #[default]
+    Full,
+    /// The middle max(a.len(), b.len()) terms of Full.
+    Same,
+    /// Only the terms where the shorter input lies entirely within the longer one,
+    /// max(a.len(), b.len()) - min(a.len(), b.len()) + 1 of them.
+    Valid
+}
+
+impl ConvolveMode {
+    // The range of the full convolution of inputs of lengths n and m that this mode keeps.
+    fn range(&self, n:usize, m:usize) -> std::ops::Range<usize> {
+        let (shorter, longer) = (n.min(m), n.max(m));
+        match self {
+            ConvolveMode::Full => 0..n + m - 1,
+            ConvolveMode::Same => {
+                let start = (shorter - 1) / 2;
+                start..start + longer
+            },
+            ConvolveMode::Valid => shorter - 1..longer
+        }
+    }
+}
+
+/// The convolution of a and b, out[k] = sum over j of a[j] * b[k - j], trimmed according to mode.
+/// Short inputs are convolved directly, and once the shorter one reaches the FFT threshold of the global
+/// MulThresholds, through this thread's cached FFT plans. The FFT result carries a small rounding error.
+/// 
+/// a and b must not be empty.
+pub fn convolve<F:FftFloat>(a:ArrayView1<F>, b:ArrayView1<F>, mode:ConvolveMode) -> Array1<F> {
+    assert!(!a.is_empty() && !b.is_empty(), "Cannot convolve an empty array.");
+    // to_vec also makes strided views contiguous
+    let (a, b) = (a.to_vec(), b.to_vec());
+    let full = if a.len().min(b.len()) < MulThresholds::global().fft {
+        direct_convolve(&a, &b)
+    } else {
+        FftPlan::with_cached(FftPlan::padded_len(a.len() + b.len() - 1), |plan| plan.convolve_real(&a, &b, 1))
+    };
+    Array1::from(full[mode.range(a.len(), b.len())].to_vec())
+}
+
+/// The cross-correlation of a and b, which is the convolution of a with b reversed. In Full mode,
+/// out[k] = sum over j of a[j + k - (b.len() - 1)] * b[j], so the lag of out[k] is k - (b.len() - 1).
+/// Same and Valid trim the result like convolve does.
+/// 
+/// a and b must not be empty.
+pub fn correlate<F:FftFloat>(a:ArrayView1<F>, b:ArrayView1<F>, mode:ConvolveMode) -> Array1<F> {
+    convolve(a, b.slice(s![..;-1]), mode)
+}
+
+// The full convolution of a and b with the schoolbook loop.
+fn direct_convolve<F:FftFloat>(a:&[F], b:&[F]) -> Vec<F> {
+    let mut out = vec![F::zero(); a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (o, y) in out[i..].iter_mut().zip(b.iter()) {
+            *o += *x * *y;
+        }
+    }
+    out
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Streaming convolution with a fixed filter, for inputs too long to hold at once. The input is cut into
+// blocks of block_len samples, and every block is convolved with the filter through one FFT of a length
+// n >= block_len + filter_len - 1. The transform of the filter is computed once, in new.
+
+/// How StreamingConvolver puts the convolved blocks together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamingMethod {
+    /// Convolves each block zero padded to n and adds the last filter_len - 1 outputs onto the next block.
+    #[default]
+    OverlapAdd,
+    /// Convolves each block together with the filter_len - 1 input samples before it, cyclically, and
+    /// drops the filter_len - 1 outputs that wrapped around.
+    OverlapSave
+}
+
+/// Convolves an unbounded input with a fixed filter, block by block. Feeding the whole input through
+/// process and then calling finish gives the same samples as convolve in Full mode, up to rounding.
+#[derive(Debug, Clone)]
+pub struct StreamingConvolver<F = f64> {
+    method: StreamingMethod,
+    filter_len: usize,
+    block_len: usize,
+    // for transforms of length block_len + filter_len - 1
+    tables: RealTables<F>,
+    // the packed transform of the filter, see RealTables
+    filter_spectrum: Vec<Complex<F>>,
+    buf: Vec<Complex<F>>,
+    // input samples that do not fill a block yet
+    pending: Vec<F>,
+    // OverlapAdd: the last filter_len - 1 outputs of the previous block.
+    // OverlapSave: the last filter_len - 1 input samples.
+    overlap: Vec<F>
+}
+
+impl <F> StreamingConvolver<F>
+where F: FftFloat {
+    /// A convolver for filter, with blocks of about four times the filter length, but at least 256 samples.
+    pub fn new(filter:&Polynomial<F>, method:StreamingMethod) -> StreamingConvolver<F> {
+        Self::with_block_len(filter, method, (4 * filter.len()).max(256))
+    }
+
+    /// A convolver for filter that processes at least block_len input samples per transform. The block
+    /// length is raised to fill the padded transform length, so block_len() can be larger.
+    pub fn with_block_len(filter:&Polynomial<F>, method:StreamingMethod, block_len:usize) -> StreamingConvolver<F> {
+        assert!(block_len > 0, "Blocks need at least one sample.");
+        let filter_len = filter.len();
+        // padded lengths are even, so real sequences can use the half length transforms of RealTables
+        let n = FftPlan::padded_len(block_len + filter_len - 1);
+        let tables = RealTables::new(n);
+        let mut filter_spectrum = vec![Complex::zero(); n / 2];
+        tables.forward(filter.coeffs.as_slice().unwrap(), &mut filter_spectrum, 1);
+        StreamingConvolver{
+            method,
+            filter_len,
+            block_len: n - filter_len + 1,
+            tables,
+            filter_spectrum,
+            buf: vec![Complex::zero(); n / 2],
+            pending: Vec::new(),
+            overlap: vec![F::zero(); filter_len - 1]
+        }
+    }
+
+    /// Number of input samples per transform.
+    #[inline]
+    pub fn block_len(&self) -> usize {
+        self.block_len
+    }
+
+    #[inline]
+    pub fn filter_len(&self) -> usize {
+        self.filter_len
+    }
+
+    /// Feeds input to the convolver and returns the outputs of every block that is now complete.
+    /// The outputs of all calls, followed by those of finish, are the convolution of the whole input in order.
+    pub fn process(&mut self, input:ArrayView1<F>) -> Array1<F> {
+        self.pending.extend(input.iter());
+        let blocks = self.pending.len() / self.block_len;
+        let mut out = Vec::with_capacity(blocks * self.block_len);
+        let pending = std::mem::take(&mut self.pending);
+        let mut chunks = pending.chunks_exact(self.block_len);
+        for block in chunks.by_ref() {
+            out.extend(self.process_block(block));
+        }
+        self.pending = chunks.remainder().to_vec();
+        Array1::from(out)
+    }
+
+    /// Flushes the samples that do not fill a block and the last filter_len - 1 outputs, which only
+    /// depend on the end of the input. The convolver is then ready for a new input.
+    pub fn finish(&mut self) -> Array1<F> {
+        // zeros after the end of the input push the remaining outputs out
+        let needed = self.pending.len() + self.filter_len - 1;
+        let mut rest = std::mem::take(&mut self.pending);
+        rest.resize(needed.div_ceil(self.block_len) * self.block_len, F::zero());
+        let mut out:Vec<F> = rest.chunks_exact(self.block_len).flat_map(|block| self.process_block(block)).collect();
+        out.truncate(needed);
+        self.overlap.iter_mut().for_each(|x| *x = F::zero());
+        Array1::from(out)
+    }
+
+    // The block_len outputs for the next block_len input samples.
+    fn process_block(&mut self, block:&[F]) -> Vec<F> {
+        let overlap = self.filter_len - 1;
+        let n = self.block_len + overlap;
+        let real = &self.tables;
+        let buf = &mut self.buf;
+        match self.method {
+            StreamingMethod::OverlapAdd => {
+                real.forward(block, buf, 1);
+                let mut out = real.multiply_inverse(buf, &self.filter_spectrum, n, 1);
+                for (y, carry) in out.iter_mut().zip(self.overlap.iter()) {
+                    *y += *carry;
+                }
+                self.overlap.copy_from_slice(&out[self.block_len..]);
+                out.truncate(self.block_len);
+                out
+            },
+            StreamingMethod::OverlapSave => {
+                let mut window = std::mem::take(&mut self.overlap);
+                window.extend_from_slice(block);
+                real.forward(&window, buf, 1);
+                let out = real.multiply_inverse(buf, &self.filter_spectrum, n, 1);
+                self.overlap = window[self.block_len..].to_vec();
+                out[overlap..].to_vec()
+            }
+        }
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Multiplying many polynomials by the same one. fft_mul transforms both operands every time, a
+// PreparedPolynomial keeps the transform of the fixed one, so each product costs one forward and one
+// inverse transform.
+
+/// A real polynomial together with its transform at a fixed length, for repeated multiplication.
+#[derive(Debug)]
+pub struct PreparedPolynomial<F = f64>
+where F: FftFloat {
+    poly: Polynomial<F>,
+    tables: RealTables<F>,
+    // the packed transform of poly, see RealTables
+    spectrum: Vec<Complex<F>>
+}
+
+impl <F> PreparedPolynomial<F>
+where F: FftFloat {
+    /// Prepares p for products with polynomials of up to max_operand_len coefficients.
+    pub fn new(p:&Polynomial<F>, max_operand_len:usize) -> PreparedPolynomial<F> {
+        assert!(max_operand_len > 0, "Operands have at least one coefficient.");
+        // padded lengths are even, so real sequences can use the half length transforms of RealTables
+        let n = FftPlan::padded_len(p.len() + max_operand_len - 1);
+        let tables = RealTables::new(n);
+        let mut spectrum = vec![Complex::zero(); n / 2];
+        tables.forward(p.coeffs.as_slice().unwrap(), &mut spectrum, 1);
+        PreparedPolynomial{poly: p.copy(), tables, spectrum}
+    }
+
+    /// The polynomial that was prepared.
+    #[inline]
+    pub fn polynomial(&self) -> &Polynomial<F> {
+        &self.poly
+    }
+
+    /// The transform length.
+    // there is no empty transform, so there is no is_empty
+    #[allow(clippy::len_without_is_empty)]
+    #[inline]
+    pub fn len(&self) -> usize {
+        2 * self.spectrum.len()
+    }
+
+    /// The most coefficients an operand of mul can have without falling back to fft_mul.
+    /// At least the max_operand_len given to new, and more if the padding left room.
+    #[inline]
+    pub fn max_operand_len(&self) -> usize {
+        self.len() + 1 - self.poly.len()
+    }
+
+    /// The values of the prepared polynomial at the len()-th roots of unity, like get_value_repr_at(len()).
+    /// Computed from the stored transform, without transforming again.
+    pub fn get_value_repr(&self) -> Array1<Complex<F>> {
+        let h = self.spectrum.len();
+        let half:Vec<Complex<F>> = (0..=h).map(|m| self.tables.spectrum(&self.spectrum, m)).collect();
+        // X_(n-m) = conj(X_m) for real polynomials
+        (0..2 * h).map(|m| if m <= h {half[m]} else {half[2 * h - m].conj()}).collect()
+    }
+
+    /// Product of the prepared polynomial and other. Only other is transformed. Operands longer than
+    /// max_operand_len() go through fft_mul instead. For rounding, see fft_mul.
+    pub fn mul(&self, other:&Polynomial<F>, rounding:impl Into<RoundingPolicy>) -> Polynomial<F> {
+        let mut buf = vec![Complex::zero(); self.spectrum.len()];
+        self.mul_with_buffer(other, rounding.into(), &mut buf)
+    }
+
+    /// mul for every polynomial in others, split across the threads that parallelism allows.
+    /// The products are in the same order as others, and identical to those of mul.
+    pub fn mul_many(&self, others:&[Polynomial<F>], rounding:impl Into<RoundingPolicy>, parallelism:Parallelism) -> Vec<Polynomial<F>> {
+        let rounding = rounding.into();
+        let threads = parallelism.threads().min(others.len()).max(1);
+        let run = |chunk:&[Polynomial<F>]| {
+            let mut buf = vec![Complex::zero(); self.spectrum.len()];
+            chunk.iter().map(|other| self.mul_with_buffer(other, rounding, &mut buf)).collect::<Vec<_>>()
+        };
+        if threads == 1 {
+            return run(others)
+        }
+        thread::scope(|s| {
+            let handles:Vec<_> = others.chunks(others.len().div_ceil(threads))
+                .map(|chunk| s.spawn(move || run(chunk))).collect();
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        })
+    }
+
+    fn mul_with_buffer(&self, other:&Polynomial<F>, rounding:RoundingPolicy, buf:&mut [Complex<F>]) -> Polynomial<F> {
+        if self.poly.deg() == 0 || other.deg() == 0 {
+            return self.poly.multiply(other)
+        }
+        if other.len() > self.max_operand_len() {
+            return self.poly.fft_mul(other, rounding)
+        }
+        let out_len = self.poly.len() + other.len() - 1;
+        self.tables.forward(other.coeffs.as_slice().unwrap(), buf, 1);
+        Polynomial::from_rounded(self.tables.multiply_inverse(buf, &self.spectrum, out_len, 1), rounding)
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// GCD over fields. In a field every nonzero coefficient can be divided by, so each division step cancels
+// the leading term and the Euclidean algorithm always terminates with the greatest common divisor.
+
+/// Coefficient types in which every nonzero element has an inverse, like f64, Complex64 and ModInt.
+/// gcd, xgcd and lcm need it, since over the integers x + 1 does not even divide 2x + 2 step by step.
+/// 
+/// To use them on Polynomial<T> for your own T, e.g. num_rational::Ratio<i64>, add `impl Field for T {}`
+/// next to its MulDispatch impl.
+pub trait Field: MulDispatch {}
+
+impl Field for f32 {}
+impl Field for f64 {}
+impl <F> Field for Complex<F> where F: FftFloat {}
+impl <P:NttPrime> Field for ModInt<P> {}
+
+impl <T> Polynomial<T> 
+    where T: Field
+{
+    /// self divided by its leading coefficient. The zero polynomial stays zero.
+    pub fn monic(&self) -> Polynomial<T> {
+        let lead = self.highest_coeff();
+        if lead.is_zero() {
+            return self.copy()
+        }
+        Polynomial{coeffs: self.coeffs.mapv(|c| c / lead)}
+    }
+
+    /// Greatest common divisor by the Euclidean algorithm. The result is monic, and gcd(0, 0) = 0.
+    /// For floating point coefficients, see gcd_with_tolerance.
+    #[inline]
+    pub fn gcd(&self, p:&Polynomial<T>) -> Polynomial<T> {
+        self._gcd_by(p, |c| c.is_zero())
+    }
+
+    /// Extended Euclidean algorithm. Returns (g, s, t) with s * self + t * p = g, where g = self.gcd(p).
+    /// deg(s) < deg(p) and deg(t) < deg(self) unless one of them divides the other.
+    #[inline]
+    pub fn xgcd(&self, p:&Polynomial<T>) -> (Polynomial<T>, Polynomial<T>, Polynomial<T>) {
+        self._xgcd_by(p, |c| c.is_zero())
+    }
+
+    /// Least common multiple, monic. It is 0 when either polynomial is 0.
+    pub fn lcm(&self, p:&Polynomial<T>) -> Polynomial<T> {
+        if self.is_zero() || p.is_zero() {
+            return Polynomial::zero()
+        }
+        let (q, _) = self._div_rem_by(&self.gcd(p), |c| c.is_zero());
+        (&q * p).monic()
+    }
+
+    // Long division over a field. Every step drops the leading term instead of trusting the subtraction to
+    // cancel it, which it does not in floating point. Leading coefficients of the remainder that are
+    // negligible are dropped as well. p must not be 0.
+    fn _div_rem_by(&self, p:&Polynomial<T>, negligible:impl Fn(T) -> bool) -> (Polynomial<T>, Polynomial<T>) {
+        let divider_deg = p.deg();
+        if self.len() <= divider_deg {
+            return (Polynomial::zero(), self.copy())
+        }
+        let divider = p.coeffs.as_slice().unwrap();
+        let lead_inv = T::one() / p.highest_coeff();
+        let mut remainder = self.coeffs.to_vec();
+        let mut quotient = vec![T::zero(); self.len() - divider_deg];
+        for k in (0..quotient.len()).rev() {
+            // remainder has k + divider_deg + 1 coefficients here
+            let c = remainder.pop().unwrap() * lead_inv;
+            quotient[k] = c;
+            for (r, d) in remainder[k..].iter_mut().zip(divider.iter()) {
+                *r = *r - c * *d;
+            }
+        }
+        while remainder.last().is_some_and(|c| negligible(*c)) {
+            remainder.pop();
+        }
+        if remainder.is_empty() {
+            remainder.push(T::zero());
+        }
+        (Polynomial::no_leading_zeros(quotient), Polynomial::no_leading_zeros(remainder))
+    }
+
+    fn _gcd_by(&self, p:&Polynomial<T>, negligible:impl Fn(T) -> bool) -> Polynomial<T> {
+        let (mut a, mut b) = (self.copy(), p.copy());
+        while !b.is_zero() {
+            let (_, r) = a._div_rem_by(&b, &negligible);
+            a = b;
+            b = r;
+        }
+        a.monic()
+    }
+
+    fn _xgcd_by(&self, p:&Polynomial<T>, negligible:impl Fn(T) -> bool) -> (Polynomial<T>, Polynomial<T>, Polynomial<T>) {
+        // a = s_a * self + t_a * p and b = s_b * self + t_b * p all along
+        let (mut a, mut b) = (self.copy(), p.copy());
+        let (mut s_a, mut s_b) = (Polynomial::one(), Polynomial::zero());
+        let (mut t_a, mut t_b) = (Polynomial::zero(), Polynomial::one());
+        while !b.is_zero() {
+            let (q, r) = a._div_rem_by(&b, &negligible);
+            let s = s_a.minus(&(&q * &s_b));
+            let t = t_a.minus(&(&q * &t_b));
+            (a, b) = (b, r);
+            (s_a, s_b) = (s_b, s);
+            (t_a, t_b) = (t_b, t);
+        }
+        let lead = a.highest_coeff();
+        if lead.is_zero() {
+            return (a, s_a, t_a)
+        }
+        let scale = |x:Polynomial<T>| Polynomial{coeffs: x.coeffs.mapv(|c| c / lead)};
+        (a.monic(), scale(s_a), scale(t_a))
+    }
+}
+
+impl <F> Polynomial<F> 
+    where F: Field + Float
+{
+    /// gcd for floating point coefficients, where rounding keeps remainders from ever being exactly 0.
+    /// Remainder coefficients within tol times the largest coefficient of self and p count as 0,
+    /// so e.g. tol = 1e-9 finds common roots that agree to about 9 digits.
+    #[inline]
+    pub fn gcd_with_tolerance(&self, p:&Polynomial<F>, tol:F) -> Polynomial<F> {
+        self._gcd_by(p, self._negligible(p, tol))
+    }
+
+    /// xgcd with the same tolerance as gcd_with_tolerance. s * self + t * p = g then only holds up to rounding.
+    #[inline]
+    pub fn xgcd_with_tolerance(&self, p:&Polynomial<F>, tol:F) -> (Polynomial<F>, Polynomial<F>, Polynomial<F>) {
+        self._xgcd_by(p, self._negligible(p, tol))
+    }
+
+    fn _negligible(&self, p:&Polynomial<F>, tol:F) -> impl Fn(F) -> bool {
+        let largest = self.coeffs.iter().chain(p.coeffs.iter()).fold(F::zero(), |m, c| m.max(c.abs()));
+        move |c:F| c.abs() <= tol * largest
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Subresultants over integral domains, e.g. the integers. Euclid with pseudo-division stays in the ring,
+// but its coefficients grow exponentially. The subresultant PRS divides every pseudo-remainder by a factor
+// known to divide it, which keeps the growth linear and gives the resultant along the way.
+
+/// Arithmetic that reports overflow, for the subresultant PRS. Its pseudo-remainders are only divided down
+/// once they are complete, so on the way they reach about the cube of the coefficients of the sequence,
+/// which overflows fixed-width integers long before the resultant itself does. The provided methods never
+/// fail, fixed-width integers override them with checked arithmetic.
+/// 
+/// To use subresultant_prs, resultant and discriminant on Polynomial<T> for your own T, add `impl CheckedOps for T {}`.
+pub trait CheckedOps: Num + Clone + Copy + Display {
+    fn try_add(self, x:Self) -> Option<Self> {Some(self + x)}
+    fn try_sub(self, x:Self) -> Option<Self> {Some(self - x)}
+    fn try_mul(self, x:Self) -> Option<Self> {Some(self * x)}
+    fn try_div(self, x:Self) -> Option<Self> {Some(self / x)}
+}
+
+macro_rules! impl_checked_ops_int {
+    ($($t:ty),*) => {
+        $(impl CheckedOps for $t {
+            fn try_add(self, x:Self) -> Option<Self> {self.checked_add(x)}
+            fn try_sub(self, x:Self) -> Option<Self> {self.checked_sub(x)}
+            fn try_mul(self, x:Self) -> Option<Self> {self.checked_mul(x)}
+            fn try_div(self, x:Self) -> Option<Self> {self.checked_div(x)}
+        }
+
+        // the same formulas as num_complex's operators, one checked step at a time
+        impl CheckedOps for Complex<$t> {
+            fn try_add(self, x:Self) -> Option<Self> {
+                Some(Complex::new(self.re.try_add(x.re)?, self.im.try_add(x.im)?))
+            }
+
+            fn try_sub(self, x:Self) -> Option<Self> {
+                Some(Complex::new(self.re.try_sub(x.re)?, self.im.try_sub(x.im)?))
+            }
+
+            fn try_mul(self, x:Self) -> Option<Self> {
+                let re = self.re.try_mul(x.re)?.try_sub(self.im.try_mul(x.im)?)?;
+                let im = self.re.try_mul(x.im)?.try_add(self.im.try_mul(x.re)?)?;
+                Some(Complex::new(re, im))
+            }
+
+            fn try_div(self, x:Self) -> Option<Self> {
+                let norm_sqr = x.re.try_mul(x.re)?.try_add(x.im.try_mul(x.im)?)?;
+                let re = self.re.try_mul(x.re)?.try_add(self.im.try_mul(x.im)?)?;
+                let im = self.im.try_mul(x.re)?.try_sub(self.re.try_mul(x.im)?)?;
+                Some(Complex::new(re.try_div(norm_sqr)?, im.try_div(norm_sqr)?))
+            }
+        })*
+    };
+}
+
+impl_checked_ops_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl CheckedOps for f32 {}
+impl CheckedOps for f64 {}
+impl <F> CheckedOps for Complex<F> where F: FftFloat {}
+impl <P:NttPrime> CheckedOps for ModInt<P> {}
+
+impl <T> Polynomial<T> 
+    where T: CheckedOps
+{
+    /// The subresultant polynomial remainder sequence of self and p, starting with whichever of the two has
+    /// the higher degree. Each element is a constant multiple of the pseudo-remainder of the two before it,
+    /// and the last one is a gcd of self and p up to a constant factor. All divisions are exact, so this
+    /// works over any integral domain. Empty if both are 0.
+    /// 
+    /// returns: the sequence, or Err(CoefficientOverflow) when an intermediate value does not fit in T,
+    /// see CheckedOps.
+    #[inline]
+    pub fn subresultant_prs(&self, p:&Polynomial<T>) -> Result<Vec<Polynomial<T>>, PolynomialError> {
+        Ok(self._subresultants(p)?.0)
+    }
+
+    /// The resultant of self and p, the determinant of their Sylvester matrix. It is 0 exactly when
+    /// self and p have a common root, or when one of them is 0.
+    /// 
+    /// returns: the resultant, or Err(CoefficientOverflow) when an intermediate value does not fit in T.
+    /// Then it may still fit, e.g. in i64 for degree 6 and coefficients up to 4, and i128 or a field like
+    /// ModInt (see fast_resultant) can compute it.
+    pub fn resultant(&self, p:&Polynomial<T>) -> Result<T, PolynomialError> {
+        if self.is_zero() || p.is_zero() {
+            return Ok(T::zero())
+        }
+        let (prs, leading) = self._subresultants(p)?;
+        if prs.last().unwrap().deg() > 0 {
+            return Ok(T::zero())
+        }
+        let res = *leading.last().unwrap();
+        // the sequence starts with the polynomial of higher degree, and res(p, self) = (-1)^(deg(self) deg(p)) res(self, p)
+        if self.deg() < p.deg() && (self.deg() * p.deg()) % 2 == 1 {
+            Self::_try_neg(res)
+        } else {
+            Ok(res)
+        }
+    }
+
+    /// The discriminant (-1)^(n(n-1)/2) res(self, self') / lc(self) for deg(self) = n. It is 0 exactly when
+    /// self has a repeated root, e.g. b^2 - 4ac for ax^2 + bx + c. Constants have discriminant 0.
+    /// Err(CoefficientOverflow) like resultant.
+    pub fn discriminant(&self) -> Result<T, PolynomialError> {
+        let n = self.deg();
+        if n == 0 {
+            return Ok(T::zero())
+        }
+        let res = self.resultant(&self.ddx())?.try_div(self.highest_coeff()).ok_or(PolynomialError::CoefficientOverflow)?;
+        if (n * (n - 1) / 2) % 2 == 1 {
+            Self::_try_neg(res)
+        } else {
+            Ok(res)
+        }
+    }
+
+    // The subresultant PRS, together with the scalars it divides by: the principal subresultant coefficients,
+    // of which the last is the resultant when the sequence ends in a constant. Brown and Traub's recurrence.
+    fn _subresultants(&self, p:&Polynomial<T>) -> Result<(Vec<Polynomial<T>>, Vec<T>), PolynomialError> {
+        let (mut f, mut g) = if self.deg() >= p.deg() {(self.copy(), p.copy())} else {(p.copy(), self.copy())};
+        if f.is_zero() {
+            return Ok((vec![], vec![]))
+        }
+        if g.is_zero() {
+            return Ok((vec![f], vec![T::one()]))
+        }
+        let mut d = f.deg() - g.deg();
+        // h = (-1)^(d+1) prem(f, g)
+        let r = f._try_pseudo_remainder(&g)?;
+        let mut h = if d % 2 == 1 {r} else {r._try_map(Self::_try_neg)?};
+        let mut lead = g.highest_coeff();
+        let mut c = Self::_try_pow(lead, d)?;
+        let mut leading = vec![T::one(), c];
+        c = Self::_try_neg(c)?;
+        let mut prs = vec![f.copy(), g.copy()];
+        while !h.is_zero() {
+            d = g.deg() - h.deg();
+            prs.push(h.copy());
+            (f, g) = (g, h);
+            let b = Self::_try_neg(lead)?.try_mul(Self::_try_pow(c, d)?).ok_or(PolynomialError::CoefficientOverflow)?;
+            h = f._try_pseudo_remainder(&g)?._try_map(|x| x.try_div(b).ok_or(PolynomialError::CoefficientOverflow))?;
+            lead = g.highest_coeff();
+            c = if d > 1 {
+                Self::_try_pow(Self::_try_neg(lead)?, d)?.try_div(Self::_try_pow(c, d - 1)?).ok_or(PolynomialError::CoefficientOverflow)?
+            } else {
+                Self::_try_neg(lead)?
+            };
+            leading.push(Self::_try_neg(c)?);
+        }
+        Ok((prs, leading))
+    }
+
+    // The remainder of pseudo_divide, with checked arithmetic
+    fn _try_pseudo_remainder(&self, p:&Polynomial<T>) -> Result<Polynomial<T>, PolynomialError> {
+        let overflow = PolynomialError::CoefficientOverflow;
+        let divider_deg = p.deg();
+        let divider = p.coeffs.as_slice().unwrap();
+        let lead = p.highest_coeff();
+        let mut remainder = self.coeffs.to_vec();
+        for k in (0..self.len() - divider_deg).rev() {
+            let c = remainder.pop().unwrap();
+            for r in remainder.iter_mut() {
+                *r = r.try_mul(lead).ok_or(overflow)?;
+            }
+            for (r, d) in remainder[k..].iter_mut().zip(divider.iter()) {
+                *r = r.try_sub(c.try_mul(*d).ok_or(overflow)?).ok_or(overflow)?;
+            }
+        }
+        if remainder.is_empty() {
+            remainder.push(T::zero());
+        }
+        Ok(Polynomial::no_leading_zeros(remainder))
+    }
+
+    fn _try_map(&self, f:impl Fn(T) -> Result<T, PolynomialError>) -> Result<Polynomial<T>, PolynomialError> {
+        let coeffs = self.coeffs.iter().map(|x| f(*x)).collect::<Result<Vec<T>, PolynomialError>>()?;
+        Ok(Polynomial::no_leading_zeros(coeffs))
+    }
+
+    fn _try_neg(x:T) -> Result<T, PolynomialError> {
+        T::zero().try_sub(x).ok_or(PolynomialError::CoefficientOverflow)
+    }
+
+    fn _try_pow(x:T, n:usize) -> Result<T, PolynomialError> {
+        (0..n).try_fold(T::one(), |acc, _| acc.try_mul(x)).ok_or(PolynomialError::CoefficientOverflow)
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Half-GCD over fields. Most Euclidean steps on a and b only depend on the top coefficients, so the first
+// half of them can be found recursively from a / x^m and b / x^m, with m = ceil(deg(a) / 2), and applied all at
+// once as a 2x2 matrix of polynomials. With fast multiplication, gcd and resultant take O(M(n) log n).
+
+// Below this degree, half_gcd takes the Euclidean steps one at a time.
+const HALF_GCD_DEFAULT:usize = 128;
+
+// [r00, r01, r10, r11], the 2x2 matrix taking (a, b) to (r00 a + r01 b, r10 a + r11 b)
+type GcdMatrix<T> = [Polynomial<T>; 4];
+
+impl <T> Polynomial<T> 
+    where T: Field
+{
+    /// gcd with the half-GCD algorithm, O(M(n) log n) instead of the O(n^2) of gcd. Uses the same fast
+    /// multiplication as p * q, so it pays off for exact fields like ModInt at large degrees. With floating point
+    /// coefficients it always terminates, but like gcd it can not tell rounding from a remainder, so
+    /// gcd_with_tolerance is usually what you want there.
+    #[inline]
+    pub fn fast_gcd(&self, p:&Polynomial<T>) -> Polynomial<T> {
+        self.fast_gcd_with_cutoff(p, HALF_GCD_DEFAULT)
+    }
+
+    /// fast_gcd that takes single Euclidean steps once the degree is below cutoff.
+    pub fn fast_gcd_with_cutoff(&self, p:&Polynomial<T>, cutoff:usize) -> Polynomial<T> {
+        Self::_fast_euclid(self.copy(), p.copy(), cutoff, &mut Vec::new()).monic()
+    }
+
+    /// The resultant of self and p, with the remainder sequence of fast_gcd. Same value as resultant,
+    /// in O(M(n) log n) instead of O(n^2), but only over fields.
+    pub fn fast_resultant(&self, p:&Polynomial<T>) -> T {
+        if self.is_zero() || p.is_zero() {
+            return T::zero()
+        }
+        // res(p, self) = (-1)^(deg(self) deg(p)) res(self, p)
+        let (a, b, sign) = if self.deg() >= p.deg() {
+            (self.copy(), p.copy(), false)
+        } else {
+            (p.copy(), self.copy(), (self.deg() * p.deg()) % 2 == 1)
+        };
+        let mut remainders = vec![(a.deg(), a.highest_coeff()), (b.deg(), b.highest_coeff())];
+        Self::_fast_euclid(a, b, HALF_GCD_DEFAULT, &mut remainders);
+        let (last_deg, _) = *remainders.last().unwrap();
+        if last_deg > 0 {
+            return T::zero()
+        }
+        // res(r_(i-1), r_i) = (-1)^(n_(i-1) n_i) lc(r_i)^(n_(i-1) - n_(i+1)) res(r_i, r_(i+1)), where n_i = deg(r_i),
+        // down to res(r_(k-1), r_k) = r_k^(n_(k-1)) for the constant r_k
+        let res = (1..remainders.len()).fold(T::one(), |acc, i| {
+            let (prev_deg, _) = remainders[i - 1];
+            let (deg, lead) = remainders[i];
+            let next_deg = remainders.get(i + 1).map_or(0, |r| r.0);
+            let factor = num_traits::pow(lead, prev_deg - next_deg);
+            if (prev_deg * deg) % 2 == 1 {acc * (T::zero() - factor)} else {acc * factor}
+        });
+        if sign {T::zero() - res} else {res}
+    }
+
+    // The Euclidean algorithm on deg(a) >= deg(b), with half_gcd doing most of the steps. Returns the last nonzero
+    // remainder and appends the degree and leading coefficient of every remainder after a and b to remainders.
+    fn _fast_euclid(mut a:Polynomial<T>, mut b:Polynomial<T>, cutoff:usize, remainders:&mut Vec<(usize, T)>) -> Polynomial<T> {
+        if a.deg() < b.deg() {
+            (a, b) = (b, a);
+        }
+        let step = |a:Polynomial<T>, b:Polynomial<T>, remainders:&mut Vec<(usize, T)>| {
+            let (_, r) = a.div_rem(&b).unwrap();
+            if !r.is_zero() {
+                remainders.push((r.deg(), r.highest_coeff()));
+            }
+            (b, r)
+        };
+        // half_gcd needs deg(a) > deg(b)
+        if !b.is_zero() {
+            (a, b) = step(a, b, remainders);
+        }
+        while !b.is_zero() {
+            let mut trail = Vec::new();
+            let matrix = Self::_half_gcd(&a, &b, cutoff.max(1), &mut trail);
+            if let Some(&(c_deg, _)) = trail.last() {
+                // trail starts with b, which is already there
+                remainders.extend(trail.into_iter().skip(1));
+                let m = a.deg().div_ceil(2);
+                (a, b) = Self::_apply(&matrix, &a, &b, c_deg + 1, m.min(c_deg));
+                if b.is_zero() {
+                    break
+                }
+                remainders.push((b.deg(), b.highest_coeff()));
+            }
+            (a, b) = step(a, b, remainders);
+        }
+        a
+    }
+
+    // For deg(a) > deg(b), the matrix of the Euclidean steps that take (a, b) to (c, d) with
+    // deg(c) >= m > deg(d), m = ceil(deg(a) / 2). trail gets the degrees and leading coefficients of the
+    // remainders from b up to c, relative to a, i.e. nothing when (c, d) = (a, b).
+    fn _half_gcd(a:&Polynomial<T>, b:&Polynomial<T>, cutoff:usize, trail:&mut Vec<(usize, T)>) -> GcdMatrix<T> {
+        // deg(a) > deg(b) >= 0 makes m at least 1, so the zero polynomial, of deg 0, always ends the steps
+        let m = a.deg().div_ceil(2);
+        if b.deg() < m {
+            return Self::_identity()
+        }
+        if a.deg() < cutoff {
+            let mut matrix = Self::_identity();
+            let (mut c, mut d) = (a.copy(), b.copy());
+            while d.deg() >= m && !d.is_zero() {
+                trail.push((d.deg(), d.highest_coeff()));
+                let (q, e) = c._div_rem_by(&d, |x| x.is_zero());
+                matrix = Self::_mat_mul(&Self::_quotient_step(q), &matrix);
+                (c, d) = (d, e);
+            }
+            return matrix
+        }
+        // the first half of the steps, from the top halves of a and b
+        let mut inner = Vec::new();
+        let first = Self::_half_gcd(&a._shift_down(m), &b._shift_down(m), cutoff, &mut inner);
+        trail.extend(inner.into_iter().map(|(deg, lead)| (deg + m, lead)));
+        let c_len = trail.last().map_or(a.len(), |(deg, _)| deg + 1);
+        let (c, d) = Self::_apply(&first, a, b, c_len, c_len - 1);
+        if d.deg() < m || d.is_zero() {
+            return first
+        }
+        // one step by hand
+        trail.push((d.deg(), d.highest_coeff()));
+        let (q, e) = c._div_rem_by(&d, |x| x.is_zero());
+        let matrix = Self::_mat_mul(&Self::_quotient_step(q), &first);
+        if e.deg() < m || e.is_zero() {
+            return matrix
+        }
+        // and the rest from the top parts of d and e, which now have about deg(a) / 2 steps left
+        let k = 2 * m - d.deg();
+        let mut inner = Vec::new();
+        let second = Self::_half_gcd(&d._shift_down(k), &e._shift_down(k), cutoff, &mut inner);
+        trail.extend(inner.into_iter().map(|(deg, lead)| (deg + k, lead)));
+        Self::_mat_mul(&second, &matrix)
+    }
+
+    // self / x^k, dropping the k lowest coefficients
+    fn _shift_down(&self, k:usize) -> Polynomial<T> {
+        if k >= self.len() {
+            return Polynomial::zero()
+        }
+        Polynomial{coeffs: self.coeffs.slice(s![k..]).to_owned()}
+    }
+
+    fn _identity() -> GcdMatrix<T> {
+        [Polynomial::one(), Polynomial::zero(), Polynomial::zero(), Polynomial::one()]
+    }
+
+    // (c, d) -> (d, c - q d)
+    fn _quotient_step(q:Polynomial<T>) -> GcdMatrix<T> {
+        [Polynomial::zero(), Polynomial::one(), Polynomial::one(), Polynomial::zero().minus(&q)]
+    }
+
+    // matrix applied to (a, b), keeping the c_len lowest coefficients of the first output and the d_len lowest
+    // of the second. The trail tells the true degrees, and with floating point coefficients the products leave
+    // rounding errors above them, which would keep the degrees from ever falling.
+    fn _apply(matrix:&GcdMatrix<T>, a:&Polynomial<T>, b:&Polynomial<T>, c_len:usize, d_len:usize) -> (Polynomial<T>, Polynomial<T>) {
+        let [r00, r01, r10, r11] = matrix;
+        let c = (r00 * a).plus(&(r01 * b))._truncate(c_len);
+        let d = (r10 * a).plus(&(r11 * b))._truncate(d_len);
+        (c, d)
+    }
+
+    fn _mat_mul(x:&GcdMatrix<T>, y:&GcdMatrix<T>) -> GcdMatrix<T> {
+        let [x00, x01, x10, x11] = x;
+        let [y00, y01, y10, y11] = y;
+        [
+            (x00 * y00).plus(&(x01 * y10)), (x00 * y01).plus(&(x01 * y11)),
+            (x10 * y00).plus(&(x11 * y10)), (x10 * y01).plus(&(x11 * y11))
+        ]
+    }
+}
+
+//-------------------------------------------------------------------------------------------------------------
+// Fast division with remainder over fields. Reversing the coefficients turns a / b into a power series
+// division, rev(a) / rev(b) mod x^(deg(a) - deg(b) + 1), and 1 / rev(b) comes from Newton's iteration
+// g -> g (2 - f g), which doubles the number of correct coefficients with two multiplications.
+// Division then costs a few multiplications of the size of the quotient.
+
+impl <T> Polynomial<T> 
+    where T: Field
+{
+    /// The first n coefficients of the power series 1 / self, by Newton iteration with the multiplication of
+    /// p * q. None if the constant coefficient is 0, as the series then has no inverse. In floating point the
+    /// coefficients are only as accurate as the products, which lose precision when they grow quickly.
+    pub fn series_inverse(&self, n:usize) -> Option<Polynomial<T>> {
+        let f0 = self.coeffs[0];
+        if f0.is_zero() {
+            return None
+        }
+        let mut g = Polynomial::from_vec(vec![T::one() / f0]);
+        let mut len = 1;
+        while len < n {
+            len = (2 * len).min(n);
+            // f g = 1 + e x^(len / 2), and g - g (f g - 1) is right up to x^len
+            let mut error = (&self._truncate(len) * &g)._truncate(len);
+            error.coeffs[0] = error.coeffs[0] - T::one();
+            g = g.minus(&(&g * &error)._truncate(len));
+        }
+        Some(g._truncate(n))
+    }
+
+    /// Division with remainder, (quotient, remainder) with self = quotient * p + remainder and
+    /// deg(remainder) < deg(p). Large quotients go through series_inverse, which needs O(M(n)) instead of
+    /// the O(n^2) of divide_by. None if p is 0. Same as self / p and self % p, which panic instead.
+    pub fn div_rem(&self, p:&Polynomial<T>) -> Option<(Polynomial<T>, Polynomial<T>)> {
+        if p.is_zero() {
+            return None
+        }
+        if self.deg() < p.deg() || self.is_zero() {
+            return Some((Polynomial::zero(), self.copy()))
+        }
+        let quotient_len = self.len() - p.deg();
+        // Newton only pays off once the products it takes are faster than schoolbook
+        let shorter = quotient_len.min(p.len());
+        if T::mul_algorithm(shorter, shorter, &MulThresholds::global()) == MulAlgorithm::Schoolbook {
+            return Some(self._div_rem_by(p, |c| c.is_zero()))
+        }
+        let inverse = p._reversed().series_inverse(quotient_len).unwrap();
+        let reversed_quotient = (&self._reversed()._truncate(quotient_len) * &inverse)._truncate(quotient_len);
+        // rev(q) can have trailing zeros, which are the leading zeros of q
+        let mut coeffs = reversed_quotient.coeffs.to_vec();
+        coeffs.resize(quotient_len, T::zero());
+        coeffs.reverse();
+        let quotient = Polynomial::no_leading_zeros(coeffs);
+        // the remainder has degree < deg(p), anything above that is rounding
+        let remainder = self.minus(&(&quotient * p))._truncate(p.deg());
+        Some((quotient, remainder))
+    }
+
+    // The first n coefficients of self, i.e. self mod x^n
+    fn _truncate(&self, n:usize) -> Polynomial<T> {
+        match n {
+            0 => Polynomial::zero(),
+            _ if n >= self.len() => self.copy(),
+            _ => Polynomial::no_leading_zeros(self.coeffs.slice(s![..n]).to_vec())
+        }
+    }
+
+    // x^deg(self) self(1/x), the coefficients in reverse
+    fn _reversed(&self) -> Polynomial<T> {
+        Polynomial::no_leading_zeros(self.coeffs.iter().rev().copied().collect())
+    }
 }